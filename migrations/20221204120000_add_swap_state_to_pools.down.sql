@@ -0,0 +1,3 @@
+ALTER TABLE pools
+  DROP COLUMN sqrt_price,
+  DROP COLUMN tick;
@@ -0,0 +1,3 @@
+ALTER TABLE pools
+  ADD COLUMN sqrt_price varchar(255) NOT NULL DEFAULT '',
+  ADD COLUMN tick varchar(20) NOT NULL DEFAULT '';
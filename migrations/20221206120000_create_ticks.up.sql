@@ -0,0 +1,9 @@
+CREATE TABLE ticks (
+  id varchar(255) PRIMARY KEY,
+  pool_id varchar(255) REFERENCES pools NOT NULL,
+  tick_idx varchar(20) NOT NULL,
+  liquidity_net varchar(255) NOT NULL,
+  liquidity_gross varchar(255) NOT NULL
+);
+
+CREATE INDEX idx_ticks_on_pool_id ON ticks(pool_id);
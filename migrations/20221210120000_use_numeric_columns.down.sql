@@ -0,0 +1,25 @@
+ALTER TABLE ticks
+  ALTER COLUMN tick_idx TYPE varchar(20),
+  ALTER COLUMN liquidity_net TYPE varchar(255),
+  ALTER COLUMN liquidity_gross TYPE varchar(255);
+
+ALTER TABLE pools
+  ALTER COLUMN token0_price TYPE varchar(255),
+  ALTER COLUMN token1_price TYPE varchar(255),
+  ALTER COLUMN total_value_locked_token0 TYPE varchar(255),
+  ALTER COLUMN total_value_locked_token1 TYPE varchar(255),
+  ALTER COLUMN liquidity TYPE varchar(255),
+  ALTER COLUMN fee_tier TYPE varchar(20),
+  ALTER COLUMN token0_balance TYPE varchar(255) USING COALESCE(token0_balance::text, ''),
+  ALTER COLUMN token0_balance SET NOT NULL,
+  ALTER COLUMN token1_balance TYPE varchar(255) USING COALESCE(token1_balance::text, ''),
+  ALTER COLUMN token1_balance SET NOT NULL,
+  ALTER COLUMN sqrt_price TYPE varchar(255) USING COALESCE(sqrt_price::text, ''),
+  ALTER COLUMN sqrt_price SET DEFAULT '',
+  ALTER COLUMN sqrt_price SET NOT NULL,
+  ALTER COLUMN tick TYPE varchar(20) USING COALESCE(tick::text, ''),
+  ALTER COLUMN tick SET DEFAULT '',
+  ALTER COLUMN tick SET NOT NULL;
+
+ALTER TABLE tokens
+  ALTER COLUMN decimals TYPE varchar(20);
@@ -0,0 +1,25 @@
+ALTER TABLE tokens
+  ALTER COLUMN decimals TYPE SMALLINT USING decimals::smallint;
+
+ALTER TABLE pools
+  ALTER COLUMN token0_price TYPE NUMERIC USING token0_price::numeric,
+  ALTER COLUMN token1_price TYPE NUMERIC USING token1_price::numeric,
+  ALTER COLUMN total_value_locked_token0 TYPE NUMERIC USING total_value_locked_token0::numeric,
+  ALTER COLUMN total_value_locked_token1 TYPE NUMERIC USING total_value_locked_token1::numeric,
+  ALTER COLUMN liquidity TYPE NUMERIC(78, 0) USING liquidity::numeric,
+  ALTER COLUMN fee_tier TYPE INTEGER USING fee_tier::integer,
+  ALTER COLUMN token0_balance DROP NOT NULL,
+  ALTER COLUMN token0_balance TYPE NUMERIC(78, 0) USING NULLIF(token0_balance, '')::numeric,
+  ALTER COLUMN token1_balance DROP NOT NULL,
+  ALTER COLUMN token1_balance TYPE NUMERIC(78, 0) USING NULLIF(token1_balance, '')::numeric,
+  ALTER COLUMN sqrt_price DROP DEFAULT,
+  ALTER COLUMN sqrt_price DROP NOT NULL,
+  ALTER COLUMN sqrt_price TYPE NUMERIC(78, 0) USING NULLIF(sqrt_price, '')::numeric,
+  ALTER COLUMN tick DROP DEFAULT,
+  ALTER COLUMN tick DROP NOT NULL,
+  ALTER COLUMN tick TYPE INTEGER USING NULLIF(tick, '')::integer;
+
+ALTER TABLE ticks
+  ALTER COLUMN tick_idx TYPE INTEGER USING tick_idx::integer,
+  ALTER COLUMN liquidity_net TYPE NUMERIC(39, 0) USING liquidity_net::numeric,
+  ALTER COLUMN liquidity_gross TYPE NUMERIC(39, 0) USING liquidity_gross::numeric;
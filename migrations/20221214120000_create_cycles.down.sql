@@ -0,0 +1,2 @@
+DROP TABLE cycle_legs;
+DROP TABLE cycles;
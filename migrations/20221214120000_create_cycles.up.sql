@@ -0,0 +1,26 @@
+CREATE TABLE cycles (
+  id varchar(255) PRIMARY KEY,
+  root_token_id varchar(255) NOT NULL,
+  router_path text[] NOT NULL,
+  input_amount NUMERIC NOT NULL,
+  expected_output NUMERIC NOT NULL,
+  projected_profit NUMERIC NOT NULL,
+  quote_profit NUMERIC NOT NULL,
+  block_number BIGINT,
+  created_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX idx_cycles_on_created_at ON cycles(created_at);
+
+CREATE TABLE cycle_legs (
+  id varchar(255) PRIMARY KEY,
+  cycle_id varchar(255) REFERENCES cycles ON DELETE CASCADE NOT NULL,
+  position INTEGER NOT NULL,
+  pool_id varchar(255) NOT NULL,
+  token_in_id varchar(255) NOT NULL,
+  token_out_id varchar(255) NOT NULL,
+  fee_tier INTEGER NOT NULL
+);
+
+CREATE INDEX idx_cycle_legs_on_cycle_id ON cycle_legs(cycle_id);
+CREATE INDEX idx_cycle_legs_on_pool_id ON cycle_legs(pool_id);
@@ -0,0 +1 @@
+ALTER TABLE pools DROP COLUMN block_number;
@@ -0,0 +1 @@
+ALTER TABLE pools ADD COLUMN block_number BIGINT;
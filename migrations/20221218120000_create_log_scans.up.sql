@@ -0,0 +1,5 @@
+CREATE TABLE log_scans (
+  id varchar(255) PRIMARY KEY,
+  last_scanned_block BIGINT NOT NULL,
+  updated_at TIMESTAMPTZ NOT NULL
+);
@@ -0,0 +1,3 @@
+ALTER TABLE pools
+  DROP COLUMN active,
+  DROP COLUMN last_seen_at;
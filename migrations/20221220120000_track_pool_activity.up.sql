@@ -0,0 +1,3 @@
+ALTER TABLE pools
+  ADD COLUMN active BOOLEAN NOT NULL DEFAULT TRUE,
+  ADD COLUMN last_seen_at TIMESTAMPTZ;
@@ -0,0 +1 @@
+ALTER TABLE tokens ADD COLUMN name varchar(255);
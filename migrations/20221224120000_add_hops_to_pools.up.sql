@@ -0,0 +1 @@
+ALTER TABLE pools ADD COLUMN hops INTEGER;
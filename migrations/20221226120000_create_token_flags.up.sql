@@ -0,0 +1,6 @@
+CREATE TABLE token_flags (
+  id varchar(255) PRIMARY KEY,
+  reason varchar(255) NOT NULL,
+  source varchar(255) NOT NULL,
+  flagged_at TIMESTAMPTZ NOT NULL
+);
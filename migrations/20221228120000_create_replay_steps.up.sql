@@ -0,0 +1,13 @@
+CREATE TABLE replay_steps (
+  id varchar(255) PRIMARY KEY,
+  replay_id varchar(255) NOT NULL,
+  block_number BIGINT NOT NULL,
+  max_depth INTEGER NOT NULL,
+  min_root_amount BIGINT NOT NULL,
+  n_cycles INTEGER NOT NULL,
+  top_quote_profits NUMERIC[] NOT NULL,
+  top_symbol_paths text[] NOT NULL,
+  created_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX idx_replay_steps_on_replay_id ON replay_steps(replay_id);
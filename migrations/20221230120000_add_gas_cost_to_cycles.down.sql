@@ -0,0 +1,5 @@
+ALTER TABLE replay_steps DROP COLUMN top_quote_net_profits;
+ALTER TABLE cycles DROP COLUMN quote_net_profit;
+ALTER TABLE cycles DROP COLUMN net_profit;
+ALTER TABLE cycles DROP COLUMN gas_cost;
+ALTER TABLE cycles DROP COLUMN gas_used;
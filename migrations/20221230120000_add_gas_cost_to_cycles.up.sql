@@ -0,0 +1,5 @@
+ALTER TABLE cycles ADD COLUMN gas_used BIGINT;
+ALTER TABLE cycles ADD COLUMN gas_cost NUMERIC;
+ALTER TABLE cycles ADD COLUMN net_profit NUMERIC;
+ALTER TABLE cycles ADD COLUMN quote_net_profit NUMERIC;
+ALTER TABLE replay_steps ADD COLUMN top_quote_net_profits NUMERIC[] NOT NULL DEFAULT '{}';
@@ -0,0 +1 @@
+ALTER TABLE replay_steps DROP COLUMN used_ticks;
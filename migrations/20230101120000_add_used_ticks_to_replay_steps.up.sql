@@ -0,0 +1 @@
+ALTER TABLE replay_steps ADD COLUMN used_ticks BOOLEAN NOT NULL DEFAULT false;
@@ -0,0 +1 @@
+ALTER TABLE replay_steps RENAME COLUMN min_quote_amount TO min_root_amount;
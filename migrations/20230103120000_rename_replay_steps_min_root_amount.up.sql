@@ -0,0 +1 @@
+ALTER TABLE replay_steps RENAME COLUMN min_root_amount TO min_quote_amount;
@@ -1,137 +1,225 @@
-use std::{env, time::Duration};
-
-use ethers_core::types::U256;
-use serde::Deserialize;
-use serde_json::{json, Value};
+use ethers_core::{
+    abi::{self, Token},
+    types::{Address, Bytes, U256},
+    utils::id,
+};
 use tracing::{error, info};
 
 use crate::{
+    config::Config,
     db::db_connection,
     models::{Model, Pool},
+    multicall::{self, Call3},
+    rpc::{RPCClient, RPCError, Request},
 };
 
-const MAX_BATCH_REQUESTS: usize = 100;
-const BALANCE_OF_SELECTOR: &str = "0x70a08231000000000000000000000000"; // sha3('balanceOf(address)').slice(0,10) + 000000000000000000000000
-const TOKEN0_REQ_ID_PREFIX: &str = "token0_";
-const TOKEN1_REQ_ID_PREFIX: &str = "token1_";
+const BALANCE_OF_SIGNATURE: &str = "balanceOf(address)";
 
-#[derive(Deserialize, Debug)]
-struct BalanceRPCReponse {
-    id: String,
-    result: U256,
-}
-
-pub async fn find_and_update_all_balances() {
+pub async fn find_and_update_all_balances(config: &Config) {
     let db_pool = db_connection().await;
-    let mut req_bodies: Vec<Value> = vec![];
-    let mut cur_req_body: Vec<Value> = vec![];
-    let pools = fetch_all_pools(&db_pool).await;
-
-    for pool in pools {
-        cur_req_body.push(balance_rpc_body(
-            &pool.id,
-            &pool.token0_id,
-            TOKEN0_REQ_ID_PREFIX.to_string() + &pool.id,
-        ));
-        cur_req_body.push(balance_rpc_body(
-            &pool.id,
-            &pool.token1_id,
-            TOKEN1_REQ_ID_PREFIX.to_string() + &pool.id,
-        ));
-
-        if cur_req_body.len() == MAX_BATCH_REQUESTS {
-            req_bodies.push(Value::Array(cur_req_body));
-            cur_req_body = vec![];
-        }
-    }
-    if !cur_req_body.is_empty() {
-        req_bodies.push(Value::Array(cur_req_body));
-    }
-
-    let eth_node_url = env::var("PROD_ETH_NODE_URL").unwrap();
-    let n_requests = req_bodies.len();
-    for (i, body) in req_bodies.iter().enumerate() {
-        let req_client = reqwest::Client::new();
-        let res = req_client.post(&eth_node_url).json(&body).send().await;
+    let client = config.rpc_client(config.node_url());
+    let mut pools = fetch_all_pools(&db_pool).await;
 
-        if let Err(err) = res {
+    let block_number = match snapshot_block(&client, config, &pools).await {
+        Ok(block_number) => block_number,
+        Err(err) => {
             error!(
                 error = err.to_string(),
-                "Error on request, stopping further requests"
+                "[Balancer] Error fetching block number"
             );
-            break;
+            return;
         }
-        let response = res.unwrap();
-        if !response.status().is_success() {
+    };
+    info!(block_number, "[Balancer] Fetching balances");
+
+    // Two balances per pool, token0 then token1, so results map back by position
+    let balance_calls: Vec<(Address, Bytes)> = pools
+        .iter()
+        .flat_map(|pool| {
+            [
+                balance_of_call(&pool.token0_id, &pool.id),
+                balance_of_call(&pool.token1_id, &pool.id),
+            ]
+        })
+        .collect();
+
+    let results = if config.multicall {
+        fetch_balances_multicall(&client, config, balance_calls, block_number).await
+    } else {
+        fetch_balances(&client, balance_calls, block_number).await
+    };
+    let results = match results {
+        Ok(results) => results,
+        Err(err) => {
+            error!(error = err, "[Balancer] Error fetching balances");
+            return;
+        }
+    };
+
+    let mut results = results.into_iter();
+    for pool in &mut pools {
+        for (token, balance) in [
+            (&pool.token0_id, &mut pool.token0_balance),
+            (&pool.token1_id, &mut pool.token1_balance),
+        ] {
+            match results.next().unwrap() {
+                Ok(result) => *balance = Some(result),
+                Err(err) => error!(
+                    error = err,
+                    pool_id = pool.id,
+                    token_id = token,
+                    "[Balancer] Error fetching balance"
+                ),
+            }
+        }
+
+        if let Err(err) = pool.save(&db_pool).await {
             error!(
-                response_body = response.text().await.unwrap(),
-                "Bad response status, sleeping then continuing"
+                error = err.to_string(),
+                "[Balancer] Error saving pool balance"
             );
-            tokio::time::sleep(Duration::from_millis(1200)).await;
-            continue;
         }
+    }
 
-        let balance_responses = parse_rpc_balance_responses(response).await;
-        for balance_res in balance_responses {
-            let is_token_0 = &balance_res.id[0..7] == TOKEN0_REQ_ID_PREFIX;
-            let pool_id = balance_res.id.split('_').collect::<Vec<&str>>()[1];
-            match Pool::find(&db_pool, pool_id).await {
-                Ok(mut pool) => {
-                    if is_token_0 {
-                        pool.token0_balance = balance_res.result.to_string();
-                    } else {
-                        pool.token1_balance = balance_res.result.to_string();
-                    }
-                    if let Err(err) = pool.save(&db_pool).await {
-                        error!(error = err.to_string(), "Error saving pool balance");
-                    }
-                }
-                Err(err) => error!(error = err.to_string(), "Error finding pool"),
-            };
-        }
+    info!(
+        n_pools = pools.len(),
+        "[Balancer] Finished updating balances"
+    );
+}
 
-        info!("Finished processing request={}/{}", i + 1, n_requests);
-        tokio::time::sleep(Duration::from_millis(1200)).await;
+// Block on-chain reads of the stored pools are pinned to: the configured block, else the block the
+// pools were snapshotted at so the reads match the pool state, else the node's latest block
+pub async fn snapshot_block(
+    client: &RPCClient,
+    config: &Config,
+    pools: &[Pool],
+) -> Result<u64, RPCError> {
+    let pools_block = pools.iter().filter_map(|pool| pool.block_number).max();
+    match config.block.or(pools_block.map(|block| block as u64)) {
+        Some(block_number) => Ok(block_number),
+        None => client.block_number().await,
     }
 }
 
-async fn fetch_all_pools(db_pool: &sqlx::Pool<sqlx::Postgres>) -> Vec<Pool> {
-    sqlx::query_as!(Pool, "SELECT * FROM pools")
+pub async fn fetch_all_pools(db_pool: &sqlx::Pool<sqlx::Postgres>) -> Vec<Pool> {
+    sqlx::query_as::<_, Pool>("SELECT * FROM pools WHERE active")
         .fetch_all(db_pool)
         .await
         .expect("Failed to fetch all pools")
 }
 
-fn balance_rpc_body(owner: &str, token_addr: &str, rpc_id: String) -> Value {
-    json!({
-        "jsonrpc": "2.0",
-        "id": rpc_id,
-        "method": "eth_call",
-        "params": [
-            {
-                "data": BALANCE_OF_SELECTOR.to_string() + &owner[2..],
-                "to": token_addr,
-            },
-            "latest"
-        ],
-    })
+// One eth_call per balance, sent in JSON-RPC batches
+async fn fetch_balances(
+    client: &RPCClient,
+    balance_calls: Vec<(Address, Bytes)>,
+    block_number: u64,
+) -> Result<Vec<Result<U256, String>>, String> {
+    let requests: Vec<Request> = balance_calls
+        .into_iter()
+        .map(|(token, data)| Request::eth_call(None, token, data, block_number))
+        .collect();
+
+    let results = client
+        .batch::<Bytes>(requests)
+        .await
+        .map_err(|err| err.to_string())?;
+    Ok(results
+        .into_iter()
+        .map(|result| {
+            result
+                .map_err(|err| err.to_string())
+                .and_then(|output| decode_balance(&output))
+        })
+        .collect())
 }
 
-async fn parse_rpc_balance_responses(response: reqwest::Response) -> Vec<BalanceRPCReponse> {
-    let parsed_response = response.json::<Vec<Value>>().await.unwrap();
-    let (balance_responses, errors): (Vec<_>, Vec<_>) = parsed_response
+// Packs `multicall_size` balanceOf calls into each Multicall3.aggregate3 eth_call. A sub-call that
+// reverts only fails the balance it was for, a failed aggregate3 call fails all of its balances.
+async fn fetch_balances_multicall(
+    client: &RPCClient,
+    config: &Config,
+    balance_calls: Vec<(Address, Bytes)>,
+    block_number: u64,
+) -> Result<Vec<Result<U256, String>>, String> {
+    let chunks: Vec<Vec<Call3>> = balance_calls
+        .chunks(config.multicall_size.max(1))
+        .map(|chunk| {
+            chunk
+                .iter()
+                .map(|(token, data)| Call3 {
+                    target: *token,
+                    allow_failure: true,
+                    call_data: data.clone(),
+                })
+                .collect()
+        })
+        .collect();
+    let requests: Vec<Request> = chunks
         .iter()
-        .map(|val| -> Result<BalanceRPCReponse, serde_json::Error> {
-            serde_json::value::from_value(val.clone())
+        .map(|calls| {
+            Request::eth_call(
+                None,
+                config.multicall_address,
+                multicall::encode_aggregate3(calls),
+                block_number,
+            )
         })
-        .partition(Result::is_ok);
-    let balance_responses: Vec<BalanceRPCReponse> =
-        balance_responses.into_iter().map(Result::unwrap).collect();
-    let errors: Vec<serde_json::Error> = errors.into_iter().map(Result::unwrap_err).collect();
+        .collect();
 
-    for err in errors {
-        error!(error = err.to_string(), "Error parsing rpc response");
+    let results = client
+        .batch::<Bytes>(requests)
+        .await
+        .map_err(|err| err.to_string())?;
+
+    let mut balances: Vec<Result<U256, String>> = Vec::with_capacity(balance_calls.len());
+    for (calls, result) in chunks.iter().zip(results) {
+        let call_results = result.map_err(|err| err.to_string()).and_then(|output| {
+            let call_results =
+                multicall::decode_aggregate3(&output).map_err(|err| err.to_string())?;
+            if call_results.len() == calls.len() {
+                Ok(call_results)
+            } else {
+                Err(format!(
+                    "aggregate3 returned {} results for {} calls",
+                    call_results.len(),
+                    calls.len()
+                ))
+            }
+        });
+
+        match call_results {
+            Ok(call_results) => balances.extend(call_results.into_iter().map(|call_result| {
+                if call_result.success {
+                    decode_balance(&call_result.return_data)
+                } else {
+                    Err(format!("balanceOf reverted: {}", call_result.return_data))
+                }
+            })),
+            Err(err) => balances.extend(calls.iter().map(|_| Err(err.clone()))),
+        }
     }
 
-    balance_responses
+    Ok(balances)
+}
+
+fn balance_of_call(token_addr: &str, owner: &str) -> (Address, Bytes) {
+    let owner: Address = owner.parse().expect("Invalid pool address");
+    let mut data = id(BALANCE_OF_SIGNATURE).to_vec();
+    data.extend(abi::encode(&[Token::Address(owner)]));
+
+    (
+        token_addr.parse().expect("Invalid token address"),
+        Bytes::from(data),
+    )
+}
+
+fn decode_balance(output: &[u8]) -> Result<U256, String> {
+    if output.len() != 32 {
+        return Err(format!(
+            "balanceOf returned {} bytes: {}",
+            output.len(),
+            Bytes::from(output.to_vec())
+        ));
+    }
+    Ok(U256::from_big_endian(output))
 }
@@ -0,0 +1,287 @@
+use std::{str::FromStr, time::Duration};
+
+use bigdecimal::BigDecimal;
+use clap::{ArgAction, Args};
+use ethers_core::types::{Address, U256};
+
+use crate::rpc::RPCClient;
+
+const USDC_ADDRESS: &str = "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48";
+const WETH_ADDRESS: &str = "0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2";
+const UNISWAP_URL: &str = "https://api.thegraph.com/subgraphs/name/uniswap/uniswap-v3";
+const MULTICALL3_ADDRESS: &str = "0xcA11bde05977b3631167028862bE2a173976CA11";
+const UNISWAP_V3_FACTORY_ADDRESS: &str = "0x1F98431c8aD98523631AE4a59f267346ea31F984";
+const UNISWAP_V3_FACTORY_BLOCK: u64 = 12369621;
+const SWAP_ROUTER_ADDRESS: &str = "0xE592427A0AEce92De3Edee56e1E3F4e8D60e7F4D";
+
+// Options shared by every stage, each one falling back to an environment variable
+#[derive(Args, Clone, Debug)]
+pub struct Config {
+    /// Addresses of the tokens cycles start and end in, and the explorer crawls out from
+    #[arg(
+        long = "root-token",
+        env = "ROOT_TOKEN_ADDRESSES",
+        value_delimiter = ',',
+        value_parser = parse_token_address,
+        default_value = USDC_ADDRESS
+    )]
+    pub root_tokens: Vec<String>,
+
+    /// Address of the token profits of every root token are converted to for ranking
+    #[arg(
+        long,
+        env = "QUOTE_TOKEN_ADDRESS",
+        value_parser = parse_token_address,
+        default_value = USDC_ADDRESS
+    )]
+    pub quote_token: String,
+
+    /// Maximum number of swaps in a cycle
+    #[arg(long, env = "MAX_DEPTH", default_value_t = 20)]
+    pub depth: usize,
+
+    /// Quote token amount a pool must hold the equivalent of, in the token bought from it, for a
+    /// cycle to go through it. It is converted to each root token at the graph's prices.
+    #[arg(long, env = "MIN_QUOTE_AMOUNT", default_value_t = 100000)]
+    pub min_quote_amount: u32,
+
+    /// Minimum locked value of a token for the explorer to follow a pool
+    #[arg(long, env = "MIN_TVL", default_value = "1000")]
+    pub min_tvl: String,
+
+    /// Minimum locked value in USD of a pool for the explorer to follow it, the default keeps pools
+    /// whose tokens the subgraph has no USD price for
+    #[arg(long, env = "MIN_TVL_USD", default_value = "0")]
+    pub min_tvl_usd: String,
+
+    /// Minimum in-range liquidity of a pool for the explorer to follow it, the default keeps pools
+    /// whose price is outside every position
+    #[arg(long, env = "MIN_LIQUIDITY", default_value = "0")]
+    pub min_liquidity: String,
+
+    /// Maximum number of swaps between a root token and the pools the explorer crawls to
+    #[arg(long, env = "MAX_HOPS")]
+    pub max_hops: Option<u32>,
+
+    /// Tokens the explorer is limited to, besides the root tokens, all tokens when empty
+    #[arg(
+        long = "allow-token",
+        env = "ALLOW_TOKEN_ADDRESSES",
+        value_delimiter = ',',
+        value_parser = parse_token_address
+    )]
+    pub allow_tokens: Vec<String>,
+
+    /// Tokens the explorer never crawls to
+    #[arg(
+        long = "deny-token",
+        env = "DENY_TOKEN_ADDRESSES",
+        value_delimiter = ',',
+        value_parser = parse_token_address
+    )]
+    pub deny_tokens: Vec<String>,
+
+    /// File of tokens to flag, one address per line optionally followed by a comma and a reason
+    #[arg(long, env = "BLACKLIST_FILE")]
+    pub blacklist_file: Option<String>,
+
+    /// Only search cycles through pools the explorer found at most this many hops from a root token
+    #[arg(long, env = "CYCLE_MAX_HOPS")]
+    pub cycle_max_hops: Option<u32>,
+
+    /// Address of the token gas costs are priced as, through the stored pools
+    #[arg(
+        long,
+        env = "GAS_TOKEN_ADDRESS",
+        value_parser = parse_token_address,
+        default_value = WETH_ADDRESS
+    )]
+    pub gas_token: String,
+
+    /// Base fee in gwei cycles are assumed to pay, defaults to the base fee of the node's block
+    #[arg(
+        long = "base-fee-gwei",
+        env = "BASE_FEE_GWEI",
+        value_name = "GWEI",
+        value_parser = parse_gwei
+    )]
+    pub base_fee: Option<U256>,
+
+    /// Priority fee in gwei cycles are assumed to pay, defaults to the node's suggested tip
+    #[arg(
+        long = "priority-fee-gwei",
+        env = "PRIORITY_FEE_GWEI",
+        value_name = "GWEI",
+        value_parser = parse_gwei
+    )]
+    pub priority_fee: Option<U256>,
+
+    /// Wipe all pools, tokens and balances before saving the explored pools, instead of updating
+    /// them in place
+    #[arg(long, env = "FULL_REFRESH")]
+    pub full_refresh: bool,
+
+    /// Number of concurrent explorer workers
+    #[arg(long, env = "N_WORKERS", default_value_t = 20)]
+    pub workers: usize,
+
+    /// Block the explorer and balancer snapshot the chain at, defaults to the subgraph's latest
+    /// indexed block when exploring and to the block the stored pools were snapshotted at
+    #[arg(long, env = "SNAPSHOT_BLOCK")]
+    pub block: Option<u64>,
+
+    /// JSON-RPC endpoint of an Ethereum node, required to fetch balances
+    #[arg(long, env = "PROD_ETH_NODE_URL")]
+    pub node_url: Option<String>,
+
+    /// Maximum number of requests in one JSON-RPC batch
+    #[arg(long, env = "RPC_BATCH_SIZE", default_value_t = 100)]
+    pub rpc_batch_size: usize,
+
+    /// Number of times a failed JSON-RPC request is retried, with exponential backoff
+    #[arg(long, env = "RPC_MAX_RETRIES", default_value_t = 5)]
+    pub rpc_max_retries: u32,
+
+    /// Minimum delay in milliseconds between two JSON-RPC requests to the same node
+    #[arg(long, env = "RPC_INTERVAL_MS", default_value_t = 1200)]
+    pub rpc_interval_ms: u64,
+
+    /// Fetch balances through Multicall3 aggregate3 instead of one eth_call per balance
+    #[arg(long, env = "USE_MULTICALL")]
+    pub multicall: bool,
+
+    /// Number of balanceOf calls packed into each aggregate3 call
+    #[arg(long, env = "MULTICALL_SIZE", default_value_t = 500)]
+    pub multicall_size: usize,
+
+    /// Address of the Multicall3 contract
+    #[arg(long, env = "MULTICALL_ADDRESS", default_value = MULTICALL3_ADDRESS)]
+    pub multicall_address: Address,
+
+    /// Address of the Uniswap V3 factory whose PoolCreated logs are scanned
+    #[arg(long, env = "FACTORY_ADDRESS", default_value = UNISWAP_V3_FACTORY_ADDRESS)]
+    pub factory_address: Address,
+
+    /// Block the first log scan starts at, later scans resume after the last scanned block
+    #[arg(long, env = "FACTORY_START_BLOCK", default_value_t = UNISWAP_V3_FACTORY_BLOCK)]
+    pub factory_start_block: u64,
+
+    /// Number of blocks covered by each eth_getLogs request
+    #[arg(long, env = "LOG_CHUNK_SIZE", default_value_t = 10000)]
+    pub log_chunk_size: u64,
+
+    /// Only log how far stored pool prices are from the chain instead of overwriting them
+    #[arg(long, env = "POOL_STATE_CROSS_CHECK")]
+    pub cross_check: bool,
+
+    /// Relative difference between a stored and an on-chain price above which it is reported
+    #[arg(long, env = "MAX_PRICE_DEVIATION", default_value = "0.01")]
+    pub max_price_deviation: BigDecimal,
+
+    /// Recipient of the swap output, when set the SwapRouter calldata of each top cycle is logged
+    #[arg(long, env = "RECIPIENT_ADDRESS")]
+    pub recipient: Option<Address>,
+
+    /// Seconds from now until the logged swap calldata expires
+    #[arg(long, env = "SWAP_DEADLINE_SECS", default_value_t = 300)]
+    pub deadline_secs: u64,
+
+    /// JSON-RPC endpoint of a forked node (Anvil, Hardhat) the top cycles are simulated against
+    #[arg(long, env = "FORK_NODE_URL")]
+    pub fork_url: Option<String>,
+
+    /// Block the simulations run at, defaults to the fork's latest block
+    #[arg(long, env = "SIMULATION_BLOCK")]
+    pub simulation_block: Option<u64>,
+
+    /// Account the simulated swaps are sent from, required with a fork node. It must hold and have
+    /// approved the input amount.
+    #[arg(long, env = "SIMULATION_FROM_ADDRESS")]
+    pub simulation_from: Option<Address>,
+
+    /// Address of the SwapRouter the calldata is built for
+    #[arg(long, env = "SWAP_ROUTER_ADDRESS", default_value = SWAP_ROUTER_ADDRESS)]
+    pub router_address: Address,
+
+    /// Uniswap V3 subgraph endpoint
+    #[arg(long, env = "UNISWAP_URL", default_value = UNISWAP_URL)]
+    pub subgraph_url: String,
+
+    /// First block of the historical replay
+    #[arg(long, env = "REPLAY_FROM_BLOCK")]
+    pub replay_from_block: Option<u64>,
+
+    /// Last block of the historical replay, defaults to the subgraph's latest indexed block
+    #[arg(long, env = "REPLAY_TO_BLOCK")]
+    pub replay_to_block: Option<u64>,
+
+    /// Number of blocks between two replayed blocks, 300 is about an hour
+    #[arg(long, env = "REPLAY_STEP_BLOCKS", default_value_t = 300)]
+    pub replay_step: u64,
+
+    /// Fetch every pool's ticks at each replayed block, slow but needed for exact swap amounts.
+    /// Without ticks, liquidity is assumed to cover every price and profits come out inflated.
+    #[arg(long, env = "REPLAY_TICKS", default_value_t = true, action = ArgAction::Set)]
+    pub replay_ticks: bool,
+
+    /// Number of most profitable cycles recorded for each replayed block
+    #[arg(long, env = "REPLAY_TOP_CYCLES", default_value_t = 10)]
+    pub replay_top: usize,
+}
+
+impl Config {
+    pub fn node_url(&self) -> &str {
+        self.node_url
+            .as_deref()
+            .expect("--node-url or PROD_ETH_NODE_URL must be set")
+    }
+
+    // Sender of the simulated swaps, there is no default since swaps from an unfunded account revert
+    pub fn simulation_from(&self) -> Address {
+        self.simulation_from.expect(
+            "--simulation-from or SIMULATION_FROM_ADDRESS must be set to simulate on the fork",
+        )
+    }
+
+    // Whether the allowlist and denylist let the explorer crawl to `token`
+    pub fn allows_token(&self, token: &str) -> bool {
+        let listed = |tokens: &[String]| {
+            tokens
+                .iter()
+                .any(|listed| listed.eq_ignore_ascii_case(token))
+        };
+        !listed(&self.deny_tokens)
+            && (self.allow_tokens.is_empty()
+                || listed(&self.allow_tokens)
+                || listed(&self.root_tokens))
+    }
+
+    pub fn rpc_client(&self, url: &str) -> RPCClient {
+        RPCClient::new(
+            url,
+            self.rpc_batch_size,
+            self.rpc_max_retries,
+            Duration::from_millis(self.rpc_interval_ms),
+        )
+    }
+}
+
+// Token ids are stored lowercase, as the subgraph returns them, so checksummed addresses from the
+// command line are normalized to match them
+fn parse_token_address(value: &str) -> Result<String, String> {
+    value
+        .trim()
+        .parse::<Address>()
+        .map(|address| format!("{:?}", address))
+        .map_err(|err| format!("invalid token address: {}", err))
+}
+
+// Fee per gas given in gwei, kept in wei. Amounts below a wei are dropped.
+fn parse_gwei(value: &str) -> Result<U256, String> {
+    let gwei = BigDecimal::from_str(value.trim()).map_err(|err| format!("invalid fee: {}", err))?;
+    if gwei < BigDecimal::from(0) {
+        return Err("fees cannot be negative".to_string());
+    }
+    let wei = (gwei * BigDecimal::from(1_000_000_000)).with_scale(0);
+    U256::from_dec_str(&wei.to_string()).map_err(|err| format!("fee out of range: {}", err))
+}
@@ -1,194 +1,696 @@
-use std::{cmp::min, collections::HashMap};
+use std::{
+    cmp::min,
+    collections::{BTreeMap, HashMap},
+};
 
-use async_recursion::async_recursion;
-use bigdecimal::BigDecimal;
-use sqlx::query_as;
-use tracing::{error, info};
+use bigdecimal::{BigDecimal, FromPrimitive};
+use chrono::Utc;
+use ethers_core::types::{Address, Bytes, I256, U256};
+use num_bigint::BigInt;
+use tracing::{error, info, warn};
+use uuid::Uuid;
 
 use crate::{
+    config::Config,
     db::db_connection,
-    models::{Pool, Token},
+    gas::{self, GasPrice},
+    models::{self, CycleLeg, Model, Pool},
+    router::{self, ExactInputParams},
+    simulator,
+    token_graph::{GraphToken, TokenGraph},
+    v3_math::TickLiquidity,
 };
 
 type DBPool = sqlx::Pool<sqlx::Postgres>;
 
-const MAX_DEPTH: usize = 20;
-const MIN_ROOT_AMOUNT: u32 = 100000;
+const MAX_BRACKET_DOUBLINGS: usize = 64;
+// Lightest paths kept per token and swap count when searching cycles
+const PATHS_PER_TOKEN: usize = 8;
 
-struct Cycle {
-    root_token: String,
-    pools: Vec<Pool>,
-    max_price: BigDecimal,
+pub struct Cycle {
+    pub root_token: String,
+    pub pools: Vec<Pool>,
+    pub max_price: BigDecimal,
+    pub optimal_input: BigDecimal,
+    pub expected_output: BigDecimal,
+    pub expected_profit: BigDecimal,
+    // `expected_profit` converted to the quote token, comparable across root tokens
+    pub quote_profit: BigDecimal,
+    // Raw amounts behind `optimal_input` and `expected_output`
+    pub input_amount: U256,
+    pub output_amount: U256,
+    // Estimated gas of the swap at the optimal input, and its cost in the root token
+    pub gas_used: u64,
+    pub gas_cost: BigDecimal,
+    // `expected_profit` less `gas_cost`, in the root token then in the quote token
+    pub net_profit: BigDecimal,
+    pub quote_net_profit: BigDecimal,
+    // Token symbols along the cycle, for logs
+    pub symbol_path: String,
 }
 
 impl Cycle {
-    pub fn router_path(&self) -> Vec<String> {
+    // Runs `amount_in` of the root token through every pool in the cycle, returning the root token
+    // amount received at the end
+    pub fn simulate(
+        &self,
+        amount_in: U256,
+        pool_ticks: &HashMap<String, Vec<TickLiquidity>>,
+    ) -> U256 {
+        self.simulate_crossing_ticks(amount_in, pool_ticks).0
+    }
+
+    // Same as `simulate`, also returning how many initialized ticks the swaps cross in total
+    pub fn simulate_crossing_ticks(
+        &self,
+        amount_in: U256,
+        pool_ticks: &HashMap<String, Vec<TickLiquidity>>,
+    ) -> (U256, usize) {
+        let mut cur_token = self.root_token.as_str();
+        let mut amount = amount_in;
+        let mut n_crossed = 0;
+
+        for pool in &self.pools {
+            let zero_for_one = pool.is_token_0(cur_token);
+            let ticks = pool_ticks
+                .get(&pool.id)
+                .map(Vec::as_slice)
+                .unwrap_or_default();
+            let (amount_out, n_pool_crossed) = pool.swap(amount, zero_for_one, ticks);
+            amount = amount_out;
+            n_crossed += n_pool_crossed;
+            cur_token = if zero_for_one {
+                &pool.token1_id
+            } else {
+                &pool.token0_id
+            };
+        }
+
+        (amount, n_crossed)
+    }
+
+    fn profit(&self, amount_in: U256, pool_ticks: &HashMap<String, Vec<TickLiquidity>>) -> I256 {
+        I256::from_raw(self.simulate(amount_in, pool_ticks)) - I256::from_raw(amount_in)
+    }
+
+    // Finds the root token input maximising absolute profit. Profit grows while the cycle's price
+    // edge outweighs slippage and shrinks afterwards, so the peak is bracketed by doubling from one
+    // root token unit and then narrowed down with a golden-section search.
+    // Returns the raw (input, output) amounts, both zero when no input is profitable.
+    pub fn find_optimal_input(
+        &self,
+        unit_amount: U256,
+        pool_ticks: &HashMap<String, Vec<TickLiquidity>>,
+    ) -> (U256, U256) {
+        let mut upper = unit_amount;
+        let mut upper_profit = self.profit(upper, pool_ticks);
+        for _ in 0..MAX_BRACKET_DOUBLINGS {
+            let next_profit = self.profit(upper * 2, pool_ticks);
+            if next_profit <= upper_profit {
+                break;
+            }
+            upper *= 2;
+            upper_profit = next_profit;
+        }
+
+        let mut lo = if upper == unit_amount {
+            U256::zero()
+        } else {
+            upper / 2
+        };
+        let mut hi = upper * 2;
+        while hi - lo > U256::from(2) {
+            let step = (hi - lo) * 382 / 1000;
+            let (left, right) = (lo + step, hi - step);
+            if self.profit(left, pool_ticks) < self.profit(right, pool_ticks) {
+                lo = left;
+            } else {
+                hi = right;
+            }
+        }
+
+        let mut best = (U256::zero(), U256::zero());
+        let mut best_profit = I256::zero();
+        let mut amount_in = lo;
+        while amount_in <= hi {
+            let amount_out = self.simulate(amount_in, pool_ticks);
+            let profit = I256::from_raw(amount_out) - I256::from_raw(amount_in);
+            if profit > best_profit {
+                best = (amount_in, amount_out);
+                best_profit = profit;
+            }
+            amount_in += U256::one();
+        }
+
+        best
+    }
+
+    // Tokens visited by the cycle in order, starting and ending with the root token
+    pub fn token_path(&self) -> Vec<String> {
         let mut cur_token = self.root_token.clone();
-        let mut path: Vec<String> = vec![];
+        let mut path: Vec<String> = vec![cur_token.clone()];
 
         for pool in &self.pools {
-            let next_token = if pool.is_token_0(&cur_token) {
+            cur_token = if pool.is_token_0(&cur_token) {
                 pool.token1_id.clone()
             } else {
                 pool.token0_id.clone()
             };
-            path.push(cur_token);
-            path.push(pool.fee_tier.clone());
-            cur_token = next_token;
+            path.push(cur_token.clone());
         }
 
-        path.push(cur_token);
         path
     }
-}
 
-pub async fn process_cycles(root_token: Token) {
-    let mut cycles: Vec<Cycle> = vec![];
-    let mut memoized_prices: HashMap<(String, String), (BigDecimal, Vec<Pool>)> = HashMap::new();
-    let db_pool = db_connection().await;
-    let root_pools = pools_for_token(&db_pool, &root_token.id).await;
-
-    for pool in root_pools {
-        let (max_price, price_path) = find_cycle(
-            &db_pool,
-            &mut memoized_prices,
-            &root_token.id,
-            pool.clone(),
-            root_token.id.clone(),
-            vec![pool.clone()],
-        )
-        .await;
-        cycles.push(Cycle {
-            root_token: root_token.id.clone(),
-            pools: price_path,
-            max_price,
-        });
-    }
-
-    print_cycle_results(cycles);
-}
-
-#[async_recursion]
-async fn find_cycle(
-    db_pool: &DBPool,
-    memoized_prices: &mut HashMap<(String, String), (BigDecimal, Vec<Pool>)>,
-    root_token_id: &str,
-    cur_pool: Pool,
-    cur_token_id: String,
-    cur_path: Vec<Pool>,
-) -> (BigDecimal, Vec<Pool>) {
-    if cur_path.len() > MAX_DEPTH {
-        return (BigDecimal::from(0), vec![]);
-    } else if cur_token_id != root_token_id
-        && (cur_pool.token0_id == root_token_id || cur_pool.token1_id == root_token_id)
-        && cur_path.len() > 1
-    {
-        return (cur_pool.fee_price_for(&cur_token_id), vec![cur_pool]);
-    } else if memoized_prices.contains_key(&(cur_pool.id.clone(), cur_token_id.clone())) {
-        return memoized_prices
-            .get(&(cur_pool.id, cur_token_id))
-            .unwrap()
-            .clone();
-    }
-
-    let new_token_id = if cur_pool.is_token_0(&cur_token_id) {
-        cur_pool.token1_id.clone()
-    } else {
-        cur_pool.token0_id.clone()
-    };
-    let new_pools = pools_for_token(db_pool, &new_token_id).await;
-    let mut new_prices: Vec<(BigDecimal, Vec<Pool>)> = vec![];
+    pub fn router_path(&self) -> Vec<String> {
+        let token_path = self.token_path();
+        let mut path: Vec<String> = vec![];
 
-    for new_pool in new_pools {
-        if new_pool.id == cur_pool.id || cur_path.contains(&new_pool) {
-            continue;
+        for (token, pool) in token_path.iter().zip(&self.pools) {
+            path.push(token.clone());
+            path.push(pool.fee_tier.to_string());
         }
 
-        let mut new_path = cur_path.clone();
-        new_path.push(new_pool.clone());
+        path.push(token_path[token_path.len() - 1].clone());
+        path
+    }
 
-        new_prices.push(
-            find_cycle(
-                db_pool,
-                memoized_prices,
-                root_token_id,
-                new_pool,
-                new_token_id.clone(),
-                new_path,
-            )
-            .await,
-        );
+    pub fn encoded_path(&self) -> Bytes {
+        let tokens: Vec<Address> = self
+            .token_path()
+            .iter()
+            .map(|token| token.parse().expect("Invalid token address"))
+            .collect();
+        let fee_tiers: Vec<u32> = self.pools.iter().map(|pool| pool.fee_tier).collect();
+
+        router::encode_path(&tokens, &fee_tiers)
     }
 
-    let (future_max_price, mut future_pool_path) = new_prices
-        .iter()
-        .max_by_key(|v| &v.0)
-        .unwrap_or(&(BigDecimal::from(0), vec![]))
-        .clone();
-    
-    let mut price_pool_path = vec![];
-    let token_balance_result = if cur_pool.is_token_0(&cur_token_id) {
-        cur_pool.token0_balance(db_pool).await
-    } else {
-        cur_pool.token1_balance(db_pool).await
+    // SwapRouter.exactInput calldata for the optimal input, reverting unless at least the input
+    // amount comes back
+    pub fn exact_input_calldata(&self, recipient: Address, deadline: U256) -> Bytes {
+        router::encode_exact_input(&ExactInputParams {
+            path: self.encoded_path(),
+            recipient,
+            deadline,
+            amount_in: self.input_amount,
+            amount_out_minimum: self.input_amount,
+        })
+    }
+}
+
+pub async fn process_cycles(config: &Config) {
+    // Checked up front so that a missing sender fails before the search rather than after it
+    let simulation = config
+        .fork_url
+        .as_ref()
+        .map(|fork_url| (fork_url, config.simulation_from()));
+    let db_pool = db_connection().await;
+    let graph = TokenGraph::load(&db_pool, config.cycle_max_hops)
+        .await
+        .expect("Failed to load token graph");
+    let block_number = match (graph.block_number, &config.node_url) {
+        (Some(block_number), _) => Some(block_number),
+        (None, Some(node_url)) => match config.rpc_client(node_url).block_number().await {
+            Ok(block_number) => Some(block_number),
+            Err(err) => {
+                error!(
+                    error = err.to_string(),
+                    "[Cycler] Error fetching block number"
+                );
+                None
+            }
+        },
+        (None, None) => None,
     };
-    let token_balance = match token_balance_result {
-        Ok(balance) => balance,
-        Err(err) => {
-            error!(pool_id = cur_pool.id, error = err.to_string(), "Error fetching token balance");
-            BigDecimal::from(0)
+    let gas_price = gas::fetch_gas_price(config, block_number).await;
+
+    let cycles = find_cycles(&graph, config, &gas_price);
+    print_cycle_results(&cycles, config);
+    if let Some((fork_url, from)) = simulation {
+        simulate_cycle_results(fork_url, from, &cycles, config).await;
+    }
+    save_cycle_results(&db_pool, &cycles, block_number).await;
+}
+
+// Profitable cycles through every root token priced in the quote token, most profitable after gas
+// first
+pub fn find_cycles(graph: &TokenGraph, config: &Config, gas_price: &GasPrice) -> Vec<Cycle> {
+    let quote_prices = graph.quote_prices(&config.quote_token);
+    let gas_token_price = match quote_prices.get(&config.gas_token) {
+        Some(&gas_token_price) => gas_token_price,
+        None => {
+            warn!(
+                gas_token = config.gas_token,
+                "[Cycler] gas token has no price in the quote token, leaving gas costs out"
+            );
+            0.0
         }
     };
-    
-    let cur_max_price = if &future_max_price * BigDecimal::from(MIN_ROOT_AMOUNT) >= token_balance {
-        BigDecimal::from(0)
-    } else {
-        &future_max_price * cur_pool.fee_price_for(&cur_token_id)
-    };
+    let mut cycles: Vec<Cycle> = vec![];
 
-    if cur_max_price > BigDecimal::from(0) {
-        price_pool_path.push(cur_pool.clone());
-        price_pool_path.append(&mut future_pool_path);
+    for root_token_id in &config.root_tokens {
+        let (root_token, quote_price) = match (
+            graph.tokens.get(root_token_id),
+            quote_prices.get(root_token_id),
+        ) {
+            (Some(root_token), Some(&quote_price)) => (root_token, quote_price),
+            _ => {
+                warn!(
+                    root_token_id,
+                    "[Cycler] root token has no price in the quote token, skipping"
+                );
+                continue;
+            }
+        };
+
+        cycles.append(&mut find_root_cycles(
+            graph,
+            root_token,
+            config.depth,
+            f64::from(config.min_quote_amount) / quote_price,
+            quote_price,
+            gas_price,
+            gas_token_price,
+        ));
     }
 
-    memoized_prices.insert(
-        (cur_pool.id, cur_token_id),
-        (cur_max_price.clone(), price_pool_path.clone()),
-    );
-    (cur_max_price, price_pool_path)
+    cycles.sort_by_key(|k| &k.quote_net_profit.clone() * BigDecimal::from(-1));
+    cycles
 }
 
-async fn pools_for_token(db_pool: &DBPool, token_id: &str) -> Vec<Pool> {
-    match query_as!(
-        Pool,
-        "SELECT * FROM pools WHERE token0_id=$1 OR token1_id=$1",
-        token_id
-    )
-    .fetch_all(db_pool)
-    .await
-    {
-        Ok(pools) => pools,
-        Err(err) => {
-            error!(
-                token_id,
-                error = err.to_string(),
-                "[Cycler] failed to fetch pools for token"
-            );
-            vec![]
+fn find_root_cycles(
+    graph: &TokenGraph,
+    root_token: &GraphToken,
+    max_depth: usize,
+    min_root_amount: f64,
+    quote_price: f64,
+    gas_price: &GasPrice,
+    gas_token_price: f64,
+) -> Vec<Cycle> {
+    let mut cycles: Vec<Cycle> =
+        find_negative_cycles(graph, &root_token.id, max_depth, min_root_amount)
+            .into_iter()
+            .map(|(weight, edges)| Cycle {
+                root_token: root_token.id.clone(),
+                pools: edges
+                    .iter()
+                    .map(|&edge| graph.pools[graph.edges[edge].pool].clone())
+                    .collect(),
+                max_price: BigDecimal::from_f64((-weight).exp()).unwrap_or_default(),
+                optimal_input: BigDecimal::from(0),
+                expected_output: BigDecimal::from(0),
+                expected_profit: BigDecimal::from(0),
+                quote_profit: BigDecimal::from(0),
+                input_amount: U256::zero(),
+                output_amount: U256::zero(),
+                gas_used: 0,
+                gas_cost: BigDecimal::from(0),
+                net_profit: BigDecimal::from(0),
+                quote_net_profit: BigDecimal::from(0),
+                symbol_path: String::new(),
+            })
+            .collect();
+
+    let unit_amount = U256::exp10(root_token.decimals as usize);
+    // Root token amount of one unit of the gas token
+    let gas_token_root_price =
+        BigDecimal::from_f64(gas_token_price / quote_price).unwrap_or_default();
+    let quote_price = BigDecimal::from_f64(quote_price).unwrap_or_default();
+    for cycle in &mut cycles {
+        cycle.symbol_path = cycle
+            .token_path()
+            .iter()
+            .map(|token_id| {
+                graph
+                    .tokens
+                    .get(token_id)
+                    .map_or(token_id.as_str(), |token| token.symbol.as_str())
+            })
+            .collect::<Vec<&str>>()
+            .join(" -> ");
+        let (optimal_input, expected_output) =
+            cycle.find_optimal_input(unit_amount, &graph.pool_ticks);
+        cycle.optimal_input = to_decimal_amount(optimal_input, root_token.decimals);
+        cycle.expected_output = to_decimal_amount(expected_output, root_token.decimals);
+        cycle.expected_profit = &cycle.expected_output - &cycle.optimal_input;
+        cycle.quote_profit = &cycle.expected_profit * &quote_price;
+        cycle.input_amount = optimal_input;
+        cycle.output_amount = expected_output;
+
+        let (_, n_ticks_crossed) = cycle.simulate_crossing_ticks(optimal_input, &graph.pool_ticks);
+        cycle.gas_used = gas::estimate_gas(cycle.pools.len(), n_ticks_crossed);
+        let gas_cost = to_decimal_amount(gas_price.cost(cycle.gas_used), gas::NATIVE_DECIMALS);
+        cycle.gas_cost = gas_cost * &gas_token_root_price;
+        cycle.net_profit = &cycle.expected_profit - &cycle.gas_cost;
+        cycle.quote_net_profit = &cycle.net_profit * &quote_price;
+    }
+
+    // A negative weight only bounds the price edge, slippage and fees can leave no input profitable
+    cycles.retain(|cycle| cycle.expected_profit > BigDecimal::from(0));
+    cycles
+}
+
+// Hop-bounded Bellman-Ford from the root token, keeping several paths per token. Layer `k` holds, for
+// every token, the `PATHS_PER_TOKEN` lightest simple paths of exactly `k` swaps from the root that
+// extend the paths kept in layer `k - 1`. Closing any of them with an edge back into the root yields
+// a candidate cycle, which is reported when its total weight is negative. Plain Bellman-Ford can
+// only prove that some negative cycle exists, while the layering guarantees each cycle passes the
+// root, and keeping more than one path per token lets cycles sharing a token with a lighter path
+// still be found. A cycle is only missed when more than `PATHS_PER_TOKEN` lighter paths reach one
+// of its tokens in the same number of swaps.
+// Returns `(weight, edge indices)` for every distinct negative cycle found, ordered by weight.
+fn find_negative_cycles(
+    graph: &TokenGraph,
+    root_token_id: &str,
+    max_depth: usize,
+    min_root_amount: f64,
+) -> Vec<(f64, Vec<usize>)> {
+    let mut layer: BTreeMap<&str, Vec<(f64, Vec<usize>)>> =
+        BTreeMap::from([(root_token_id, vec![(0.0, vec![])])]);
+    let mut found_cycles: BTreeMap<Vec<usize>, f64> = BTreeMap::new();
+
+    for depth in 1..=max_depth {
+        let mut next_layer: BTreeMap<&str, Vec<(f64, Vec<usize>)>> = BTreeMap::new();
+
+        for (&token_id, paths) in &layer {
+            for (dist, path) in paths {
+                for &edge_idx in graph.edges_from(token_id) {
+                    let edge = &graph.edges[edge_idx];
+                    let new_dist = dist + edge.weight;
+                    let uses_pool = path.iter().any(|&e| graph.edges[e].pool == edge.pool);
+
+                    if edge.to_token == root_token_id {
+                        if depth > 1 && new_dist < 0.0 && !uses_pool {
+                            let mut cycle = path.clone();
+                            cycle.push(edge_idx);
+                            found_cycles.insert(cycle, new_dist);
+                        }
+                        continue;
+                    }
+
+                    // The pool has to hold at least `min_root_amount` worth of the token bought
+                    // from it
+                    let root_amount_in_token = min_root_amount * (-new_dist).exp();
+                    let visits_token = path
+                        .iter()
+                        .any(|&e| graph.edges[e].from_token == edge.to_token);
+                    if uses_pool || visits_token || edge.out_balance < root_amount_in_token {
+                        continue;
+                    }
+
+                    let token_paths = next_layer.entry(edge.to_token.as_str()).or_default();
+                    if token_paths.len() == PATHS_PER_TOKEN
+                        && new_dist >= token_paths[PATHS_PER_TOKEN - 1].0
+                    {
+                        continue;
+                    }
+                    let mut new_path = path.clone();
+                    new_path.push(edge_idx);
+                    let idx = token_paths.partition_point(|&(d, _)| d <= new_dist);
+                    token_paths.insert(idx, (new_dist, new_path));
+                    token_paths.truncate(PATHS_PER_TOKEN);
+                }
+            }
+        }
+
+        if next_layer.is_empty() {
+            break;
         }
+        layer = next_layer;
     }
+
+    let mut cycles: Vec<(f64, Vec<usize>)> = found_cycles
+        .into_iter()
+        .map(|(edges, weight)| (weight, edges))
+        .collect();
+    cycles.sort_by(|a, b| a.0.total_cmp(&b.0));
+    cycles
 }
 
-fn print_cycle_results(mut cycles: Vec<Cycle>) {
-    cycles.sort_by_key(|k| &k.max_price.clone() * BigDecimal::from(-1));
+fn print_cycle_results(cycles: &[Cycle], config: &Config) {
     let n_cycles = min(cycles.len(), 10);
+    let deadline = U256::from(Utc::now().timestamp() as u64 + config.deadline_secs);
 
-    for cycle in &mut cycles[..n_cycles] {
+    for cycle in &cycles[..n_cycles] {
         let pool_ids: Vec<String> = cycle.pools.iter().map(|pool| pool.id.clone()).collect();
         info!(
-            projected_profit = format!("{:.5}", cycle.max_price),
+            root_token = cycle.root_token,
+            symbols = cycle.symbol_path,
+            quote_net_profit = format!("{:.5}", cycle.quote_net_profit),
+            quote_profit = format!("{:.5}", cycle.quote_profit),
+            projected_profit = format!("{:.5}", cycle.expected_profit),
+            net_profit = format!("{:.5}", cycle.net_profit),
+            gas_cost = format!("{:.5}", cycle.gas_cost),
+            gas_used = cycle.gas_used,
+            optimal_input = format!("{:.5}", cycle.optimal_input),
+            expected_output = format!("{:.5}", cycle.expected_output),
+            price_product = format!("{:.5}", cycle.max_price),
             length = cycle.pools.len(),
-            "path={:?} pool_ids={:?}", cycle.router_path(), pool_ids
+            "path={:?} pool_ids={:?}",
+            cycle.router_path(),
+            pool_ids
         );
+        if let Some(recipient) = config.recipient {
+            info!(
+                encoded_path = cycle.encoded_path().to_string(),
+                "calldata={}",
+                cycle.exact_input_calldata(recipient, deadline)
+            );
+        }
+    }
+}
+
+// Replays the swap of each top cycle with `eth_call` on a fork, comparing the router's output to ours
+async fn simulate_cycle_results(fork_url: &str, from: Address, cycles: &[Cycle], config: &Config) {
+    let client = config.rpc_client(fork_url);
+    let block_number = match config.simulation_block {
+        Some(block_number) => block_number,
+        None => match client.block_number().await {
+            Ok(block_number) => block_number,
+            Err(err) => {
+                error!(
+                    error = err.to_string(),
+                    "[Simulator] Error fetching fork block number"
+                );
+                return;
+            }
+        },
+    };
+    let recipient = config.recipient.unwrap_or(from);
+    let deadline = U256::from(Utc::now().timestamp() as u64 + config.deadline_secs);
+
+    for cycle in &cycles[..min(cycles.len(), 10)] {
+        // exactInput reverts on a zero input
+        if cycle.input_amount.is_zero() {
+            continue;
+        }
+        let calldata = cycle.exact_input_calldata(recipient, deadline);
+        match simulator::simulate_swap(&client, config.router_address, from, calldata, block_number)
+            .await
+        {
+            Ok(amount_out) => info!(
+                block_number,
+                symbols = cycle.symbol_path,
+                amount_in = cycle.input_amount.to_string(),
+                expected_output = cycle.output_amount.to_string(),
+                simulated_output = amount_out.to_string(),
+                "[Simulator] path={:?}",
+                cycle.router_path()
+            ),
+            Err(err) => warn!(
+                block_number,
+                symbols = cycle.symbol_path,
+                error = err.to_string(),
+                "[Simulator] Swap failed, path={:?}",
+                cycle.router_path()
+            ),
+        }
+    }
+}
+
+// Stores every cycle of this run with its legs, all sharing the run's timestamp
+async fn save_cycle_results(db_pool: &DBPool, cycles: &[Cycle], block_number: Option<u64>) {
+    let created_at = Utc::now();
+
+    for cycle in cycles {
+        let cycle_record = models::Cycle {
+            id: Uuid::new_v4().to_string(),
+            root_token_id: cycle.root_token.clone(),
+            router_path: cycle.router_path(),
+            input_amount: cycle.optimal_input.clone(),
+            expected_output: cycle.expected_output.clone(),
+            projected_profit: cycle.expected_profit.clone(),
+            quote_profit: cycle.quote_profit.clone(),
+            gas_used: Some(cycle.gas_used as i64),
+            gas_cost: Some(cycle.gas_cost.clone()),
+            net_profit: Some(cycle.net_profit.clone()),
+            quote_net_profit: Some(cycle.quote_net_profit.clone()),
+            block_number: block_number.map(|block_number| block_number as i64),
+            created_at,
+        };
+        if let Err(err) = cycle_record.save(db_pool).await {
+            error!(error = err.to_string(), "[Cycler] Error saving cycle");
+            continue;
+        }
+
+        let mut token_in = cycle.root_token.clone();
+        for (position, pool) in cycle.pools.iter().enumerate() {
+            let token_out = if pool.is_token_0(&token_in) {
+                pool.token1_id.clone()
+            } else {
+                pool.token0_id.clone()
+            };
+            let leg = CycleLeg {
+                id: format!("{}-{}", cycle_record.id, position),
+                cycle_id: cycle_record.id.clone(),
+                position: position as u32,
+                pool_id: pool.id.clone(),
+                token_in_id: token_in,
+                token_out_id: token_out.clone(),
+                fee_tier: pool.fee_tier,
+            };
+            if let Err(err) = leg.save(db_pool).await {
+                error!(error = err.to_string(), "[Cycler] Error saving cycle leg");
+            }
+            token_in = token_out;
+        }
+    }
+
+    info!(n_cycles = cycles.len(), "[Cycler] Saved cycle results");
+}
+
+fn to_decimal_amount(amount: U256, decimals: u32) -> BigDecimal {
+    BigDecimal::new(
+        amount.to_string().parse::<BigInt>().unwrap(),
+        decimals as i64,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROOT: &str = "0x0000000000000000000000000000000000000001";
+    const TOKEN: &str = "0x0000000000000000000000000000000000000002";
+
+    // Full range pool selling `TOKEN` for `ROOT` at `price`, both tokens with 18 decimals
+    fn pool(id: &str, price: f64, fee_tier: u32) -> Pool {
+        let sqrt_price = U256::from((price.sqrt() * 2f64.powi(96)) as u128);
+        Pool {
+            id: id.to_string(),
+            token0_id: ROOT.to_string(),
+            token1_id: TOKEN.to_string(),
+            token0_price: BigDecimal::from_f64(1.0 / price).unwrap(),
+            token1_price: BigDecimal::from_f64(price).unwrap(),
+            total_value_locked_token0: BigDecimal::from(1_000_000),
+            total_value_locked_token1: BigDecimal::from(1_000_000),
+            liquidity: U256::exp10(24),
+            fee_tier,
+            token0_balance: Some(U256::exp10(24)),
+            token1_balance: Some(U256::exp10(24)),
+            sqrt_price: Some(sqrt_price),
+            tick: Some((price.ln() / 1.0001f64.ln()).floor() as i32),
+            block_number: None,
+            active: true,
+            last_seen_at: None,
+            hops: None,
+        }
+    }
+
+    fn graph(pools: Vec<Pool>) -> TokenGraph {
+        let tokens = [(ROOT, "ROOT"), (TOKEN, "TKN")]
+            .into_iter()
+            .map(|(id, symbol)| {
+                let token = GraphToken {
+                    id: id.to_string(),
+                    symbol: symbol.to_string(),
+                    decimals: 18,
+                };
+                (id.to_string(), token)
+            })
+            .collect();
+        TokenGraph::new(tokens, pools, HashMap::new())
+    }
+
+    // Pool ids of every cycle found from the root, in the order they are swapped through
+    fn cycle_pool_ids(graph: &TokenGraph) -> Vec<Vec<&str>> {
+        find_negative_cycles(graph, ROOT, 3, 1.0)
+            .into_iter()
+            .map(|(_, edges)| {
+                edges
+                    .iter()
+                    .map(|&edge| graph.pools[graph.edges[edge].pool].id.as_str())
+                    .collect()
+            })
+            .collect()
+    }
+
+    // Cycle from the root through `pools` in order, before any amounts are worked out
+    fn cycle(pools: Vec<Pool>) -> Cycle {
+        Cycle {
+            root_token: ROOT.to_string(),
+            pools,
+            max_price: BigDecimal::from(0),
+            optimal_input: BigDecimal::from(0),
+            expected_output: BigDecimal::from(0),
+            expected_profit: BigDecimal::from(0),
+            quote_profit: BigDecimal::from(0),
+            input_amount: U256::zero(),
+            output_amount: U256::zero(),
+            gas_used: 0,
+            gas_cost: BigDecimal::from(0),
+            net_profit: BigDecimal::from(0),
+            quote_net_profit: BigDecimal::from(0),
+            symbol_path: String::new(),
+        }
+    }
+
+    // Both cycles buy the token cheaply and sell it to "sell", so they share the token after one swap
+    // where the path through "cheapest" is the lighter one
+    #[test]
+    fn negative_cycles_sharing_a_token_are_all_found() {
+        let graph = graph(vec![
+            pool("cheapest", 1.02, 500),
+            pool("cheap", 1.01, 500),
+            pool("sell", 1.0, 500),
+        ]);
+        let cycles = cycle_pool_ids(&graph);
+
+        assert!(cycles.contains(&vec!["cheapest", "sell"]));
+        assert!(cycles.contains(&vec!["cheap", "sell"]));
+    }
+
+    // Without fees and ticks both pools trade like constant product pools with virtual reserves
+    // `L / sqrt(P)` and `L * sqrt(P)`, and the whole cycle returns `a * dx / (b + c * dx)` for `dx`
+    // in, whose profit peaks at `dx = (sqrt(a * b) - b) / c`
+    #[test]
+    fn optimal_input_matches_closed_form() {
+        let (buy, sell) = (pool("buy", 1.02, 0), pool("sell", 1.0, 0));
+        let reserves = |pool: &Pool| {
+            let liquidity = pool.liquidity.as_u128() as f64;
+            let sqrt_price = pool.sqrt_price.unwrap().as_u128() as f64 / 2f64.powi(96);
+            (liquidity / sqrt_price, liquidity * sqrt_price)
+        };
+        let ((x1, y1), (x2, y2)) = (reserves(&buy), reserves(&sell));
+        let (a, b, c) = (x2 * y1, x1 * y2, y1 + y2);
+        let expected_input = ((a * b).sqrt() - b) / c;
+
+        let (input, output) =
+            cycle(vec![buy, sell]).find_optimal_input(U256::exp10(18), &HashMap::new());
+
+        let input = input.as_u128() as f64;
+        assert!((input - expected_input).abs() / expected_input < 1e-6);
+        assert!(output.as_u128() as f64 > input);
+    }
+
+    #[test]
+    fn optimal_input_is_zero_when_fees_outweigh_the_edge() {
+        let cycle = cycle(vec![pool("buy", 1.001, 3000), pool("sell", 1.0, 3000)]);
+
+        let (input, output) = cycle.find_optimal_input(U256::exp10(18), &HashMap::new());
+
+        assert_eq!(input, U256::zero());
+        assert_eq!(output, U256::zero());
     }
 }
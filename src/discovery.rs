@@ -0,0 +1,304 @@
+use std::collections::BTreeSet;
+
+use bigdecimal::BigDecimal;
+use chrono::Utc;
+use ethers_core::{
+    types::{Address, Log, H256, U256},
+    utils::keccak256,
+};
+use tracing::{error, info, warn};
+
+use crate::{
+    config::Config,
+    db::db_connection,
+    models::{LogScan, Model, Pool, Token},
+    pool_reader,
+    rpc::{RPCClient, Request},
+    token_metadata,
+};
+
+type DBPool = sqlx::Pool<sqlx::Postgres>;
+
+const POOL_CREATED_SIGNATURE: &str = "PoolCreated(address,address,uint24,int24,address)";
+
+// Pool announced by a PoolCreated event of the factory
+struct CreatedPool {
+    pool: Address,
+    token0: Address,
+    token1: Address,
+    fee: u32,
+}
+
+// Fills `tokens` and `pools` from the factory's PoolCreated logs instead of the subgraph, scanning
+// `eth_getLogs` in block ranges from where the previous scan stopped. New pools are stored with
+// their swap state at the scanned head block; their balances are left to the balancer.
+pub async fn discover_pools_from_logs(config: &Config) {
+    let db_pool = db_connection().await;
+    let client = config.rpc_client(config.node_url());
+    let scan_id = format!("{:?}", config.factory_address);
+
+    let from_block = match LogScan::find(&db_pool, &scan_id).await {
+        Ok(scan) => scan.last_scanned_block as u64 + 1,
+        Err(_) => config.factory_start_block,
+    };
+    let head_block = match config.block {
+        Some(block_number) => block_number,
+        None => match client.block_number().await {
+            Ok(block_number) => block_number,
+            Err(err) => {
+                error!(
+                    error = err.to_string(),
+                    "[Discovery] Error fetching block number"
+                );
+                return;
+            }
+        },
+    };
+    if from_block > head_block {
+        info!(
+            head_block,
+            "[Discovery] Already scanned up to the head block"
+        );
+        return;
+    }
+    info!(
+        from_block,
+        head_block, "[Discovery] Scanning PoolCreated logs"
+    );
+
+    let topic0 = H256::from(keccak256(POOL_CREATED_SIGNATURE));
+    let chunk_size = config.log_chunk_size.max(1);
+    let ranges: Vec<(u64, u64)> = (from_block..=head_block)
+        .step_by(chunk_size as usize)
+        .map(|start| (start, (start + chunk_size - 1).min(head_block)))
+        .collect();
+
+    // Ranges are requested a batch at a time but recorded strictly in order, so an interrupted
+    // scan resumes right after the last range that was fully stored
+    for group in ranges.chunks(config.rpc_batch_size.max(1)) {
+        let requests: Vec<Request> = group
+            .iter()
+            .map(|&(from, to)| Request::get_logs(config.factory_address, topic0, from, to))
+            .collect();
+        let results = match client.batch::<Vec<Log>>(requests).await {
+            Ok(results) => results,
+            Err(err) => {
+                error!(error = err.to_string(), "[Discovery] Error fetching logs");
+                return;
+            }
+        };
+
+        for (&(from, to), result) in group.iter().zip(results) {
+            let logs = match result {
+                Ok(logs) => logs,
+                Err(err) => {
+                    error!(
+                        error = err.to_string(),
+                        from, to, "[Discovery] Error fetching logs"
+                    );
+                    return;
+                }
+            };
+
+            let created_pools: Vec<CreatedPool> =
+                logs.iter().filter_map(parse_pool_created).collect();
+            let n_saved =
+                match save_created_pools(&db_pool, &client, &created_pools, head_block).await {
+                    Ok(n_saved) => n_saved,
+                    // The range is scanned again by the next run, pools already stored are skipped
+                    Err(err) => {
+                        error!(
+                            error = err,
+                            from, to, "[Discovery] Error reaching the node, stopping the scan"
+                        );
+                        return;
+                    }
+                };
+
+            LogScan {
+                id: scan_id.clone(),
+                last_scanned_block: to as i64,
+                updated_at: Utc::now(),
+            }
+            .save(&db_pool)
+            .await
+            .expect("Failed to save log scan progress");
+            info!(from, to, n_pools = n_saved, "[Discovery] Scanned blocks");
+        }
+    }
+}
+
+fn parse_pool_created(log: &Log) -> Option<CreatedPool> {
+    if log.topics.len() != 4 || log.data.len() != 64 {
+        error!(
+            transaction_hash = format!("{:?}", log.transaction_hash),
+            "[Discovery] Malformed PoolCreated log"
+        );
+        return None;
+    }
+
+    Some(CreatedPool {
+        token0: Address::from(log.topics[1]),
+        token1: Address::from(log.topics[2]),
+        fee: U256::from_big_endian(log.topics[3].as_bytes()).low_u32(),
+        pool: Address::from_slice(&log.data[44..64]),
+    })
+}
+
+// Stores the pools not seen before along with any new tokens, returning how many pools were saved.
+// Pools whose tokens or state cannot be read, such as pools of broken tokens, are logged and left
+// out for good, while failing to reach the node fails the whole range so that it is scanned again.
+async fn save_created_pools(
+    db_pool: &DBPool,
+    client: &RPCClient,
+    created_pools: &[CreatedPool],
+    block_number: u64,
+) -> Result<usize, String> {
+    let mut new_pools: Vec<&CreatedPool> = vec![];
+    let mut new_tokens: BTreeSet<Address> = BTreeSet::new();
+    for created_pool in created_pools {
+        if Pool::find(db_pool, &format!("{:?}", created_pool.pool))
+            .await
+            .is_ok()
+        {
+            continue;
+        }
+        for token in [created_pool.token0, created_pool.token1] {
+            if Token::find(db_pool, &format!("{:?}", token)).await.is_err() {
+                new_tokens.insert(token);
+            }
+        }
+        new_pools.push(created_pool);
+    }
+
+    let new_tokens: Vec<Address> = new_tokens.into_iter().collect();
+    let metadata =
+        token_metadata::resolve_token_metadata(client, &new_tokens, block_number).await?;
+    for (&token, metadata) in new_tokens.iter().zip(metadata) {
+        match metadata {
+            Ok(metadata) => metadata
+                .into_token(token)
+                .save(db_pool)
+                .await
+                .map(|_| ())
+                .expect("Failed to save token"),
+            Err(err) => error!(
+                error = err,
+                token_id = format!("{:?}", token),
+                "[Discovery] Error resolving token"
+            ),
+        }
+    }
+
+    let pool_ids: Vec<Address> = new_pools.iter().map(|pool| pool.pool).collect();
+    let states = pool_reader::read_pool_states(client, &pool_ids, block_number).await?;
+
+    let mut n_saved = 0;
+    for (created_pool, state) in new_pools.into_iter().zip(states) {
+        let pool_id = format!("{:?}", created_pool.pool);
+        let (token0, token1) = match (
+            Token::find(db_pool, &format!("{:?}", created_pool.token0)).await,
+            Token::find(db_pool, &format!("{:?}", created_pool.token1)).await,
+        ) {
+            (Ok(token0), Ok(token1)) => (token0, token1),
+            _ => {
+                warn!(
+                    pool_id,
+                    "[Discovery] Pool tokens could not be resolved, skipping"
+                );
+                continue;
+            }
+        };
+
+        let mut pool = Pool {
+            id: pool_id,
+            token0_id: token0.id.clone(),
+            token1_id: token1.id.clone(),
+            token0_price: BigDecimal::from(0),
+            token1_price: BigDecimal::from(0),
+            total_value_locked_token0: BigDecimal::from(0),
+            total_value_locked_token1: BigDecimal::from(0),
+            liquidity: U256::zero(),
+            fee_tier: created_pool.fee,
+            token0_balance: None,
+            token1_balance: None,
+            sqrt_price: None,
+            tick: None,
+            block_number: None,
+            active: true,
+            last_seen_at: Some(Utc::now()),
+            hops: None,
+        };
+        // Pools that were never initialized have no price yet and stay out of the token graph
+        match state {
+            Ok(state) if !state.sqrt_price_x96.is_zero() => {
+                pool_reader::apply_state(&mut pool, &state, &token0, &token1, block_number)
+            }
+            Ok(_) => (),
+            Err(err) => {
+                warn!(
+                    error = err,
+                    pool_id = pool.id,
+                    "[Discovery] Error reading pool state, skipping"
+                );
+                continue;
+            }
+        }
+
+        pool.save(db_pool).await.expect("Failed to save pool");
+        n_saved += 1;
+    }
+
+    Ok(n_saved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_pool_created_log() {
+        // Creation of the USDC/WETH 0.05% pool, as returned by eth_getLogs
+        let log: Log = serde_json::from_value(serde_json::json!({
+            "address": "0x1f98431c8ad98523631ae4a59f267346ea31f984",
+            "topics": [
+                "0x783cca1c0412dd0d695e784568c96da2e9c22ff989357a2e8b1d9b2b4e6b7118",
+                "0x000000000000000000000000a0b86991c6218b36c1d19d4a2e9eb0ce3606eb48",
+                "0x000000000000000000000000c02aaa39b223fe8d0a0e5c4f27ead9083c756cc2",
+                "0x00000000000000000000000000000000000000000000000000000000000001f4"
+            ],
+            "data": "0x000000000000000000000000000000000000000000000000000000000000000a00000000000000000000000088e6a0c2ddd26feeb64f039a2c41296fcb3f5640",
+            "blockNumber": "0xbcda99",
+            "logIndex": "0x0",
+            "removed": false
+        }))
+        .unwrap();
+        assert_eq!(log.topics[0], H256::from(keccak256(POOL_CREATED_SIGNATURE)));
+
+        let created_pool = parse_pool_created(&log).unwrap();
+        let address = |s: &str| s.parse::<Address>().unwrap();
+        assert_eq!(
+            created_pool.pool,
+            address("0x88e6a0c2ddd26feeb64f039a2c41296fcb3f5640")
+        );
+        assert_eq!(
+            created_pool.token0,
+            address("0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48")
+        );
+        assert_eq!(
+            created_pool.token1,
+            address("0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2")
+        );
+        assert_eq!(created_pool.fee, 500);
+    }
+
+    #[test]
+    fn rejects_malformed_pool_created_log() {
+        let log = Log {
+            topics: vec![H256::zero(); 3],
+            data: vec![0; 64].into(),
+            ..Default::default()
+        };
+        assert!(parse_pool_created(&log).is_none());
+    }
+}
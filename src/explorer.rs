@@ -1,162 +1,713 @@
-use std::collections::HashSet;
-use std::{cmp::min, sync::Arc};
+use std::collections::{HashMap, HashSet};
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, Mutex,
+};
+use std::{fmt, time::Duration};
 
+use chrono::{DateTime, Utc};
 use graphql_client::{GraphQLQuery, Response};
-use tokio::{sync::RwLock, task::JoinHandle};
-use tracing::{error, info};
+use reqwest::StatusCode;
+use sqlx::{Postgres, Transaction};
+use tokio::{
+    sync::mpsc::{self, UnboundedSender},
+    task::JoinHandle,
+};
+use tracing::{error, info, warn};
 
+use crate::config::Config;
 use crate::db::db_connection;
 use crate::models::{
+    column::{self, FieldError},
+    meta_query::{indexed_block, IndexedBlock},
     pool_query::{pools_for_token, PoolsForToken},
-    Model, Pool, Token,
+    tick_query::{ticks_for_pool, TicksForPool},
+    Pool, Tick, Token, TokenFlag,
 };
 
-const UNISWAP_URL: &str = "https://api.thegraph.com/subgraphs/name/uniswap/uniswap-v3";
-const N_WORKERS: usize = 20;
+const POOLS_PAGE_SIZE: i64 = 1000;
+const TICKS_PAGE_SIZE: i64 = 1000;
+const MAX_QUERY_RETRIES: u32 = 5;
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+// Times a token's pools may fail to be fetched before its branch is left unexplored
+const MAX_TOKEN_ATTEMPTS: u32 = 3;
 
-pub async fn find_and_update_all_pools(root_token_address: String) {
-    let processed_pools: Arc<RwLock<HashSet<String>>> = Arc::new(RwLock::new(HashSet::new()));
-    let processed_tokens: Arc<RwLock<HashSet<String>>> = Arc::new(RwLock::new(HashSet::new()));
-    let tokens_to_explore: Arc<RwLock<Vec<String>>> =
-        Arc::new(RwLock::new(vec![root_token_address]));
-    let db_pool = db_connection().await;
-    clear_pool_data(&db_pool).await;
+#[derive(Debug)]
+pub enum SubgraphError {
+    Transport(reqwest::Error),
+    Status(StatusCode, String),
+    GraphQL(Vec<graphql_client::Error>),
+    MissingData,
+}
 
-    while tokens_to_explore.read().await.len() > 0 {
-        let token_addrs: Vec<String> = {
-            let mut cur_tokens = tokens_to_explore.write().await;
-            let n_tokens = min(cur_tokens.len(), N_WORKERS);
-            cur_tokens.drain(0..n_tokens).collect()
-        };
+impl fmt::Display for SubgraphError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SubgraphError::Transport(err) => write!(f, "transport error: {}", err),
+            SubgraphError::Status(status, body) => write!(f, "bad status {}: {}", status, body),
+            SubgraphError::GraphQL(errors) => {
+                let messages: Vec<String> = errors.iter().map(|err| err.to_string()).collect();
+                write!(f, "graphql errors: {}", messages.join("; "))
+            }
+            SubgraphError::MissingData => write!(f, "response has no data"),
+        }
+    }
+}
 
-        let mut handles: Vec<JoinHandle<()>> = vec![];
-        for addr in token_addrs {
-            let processed_pools = processed_pools.clone();
-            let processed_tokens = processed_tokens.clone();
-            let tokens_to_explore = tokens_to_explore.clone();
-            let db_pool_clone = db_pool.clone();
-
-            handles.push(tokio::spawn(async move {
-                let pools = fetch_pools_for_token(&addr, None, None).await;
-                for pool in pools {
-                    if processed_pools.read().await.contains(&pool.id) {
-                        continue;
-                    }
-
-                    save_pool_data(&db_pool_clone, &pool).await;
-                    processed_pools.write().await.insert(pool.id.clone());
-
-                    let mut next_token = &pool.token0.id;
-                    if next_token == &addr {
-                        next_token = &pool.token1.id
-                    }
-
-                    if !processed_tokens.read().await.contains(next_token) {
-                        tokens_to_explore.write().await.push(next_token.to_string());
-                    }
-
-                    processed_tokens.write().await.insert(addr.clone());
-                    info!(pool_address = pool.id, "[Explorer] Successfully processed");
-                }
-            }));
+impl From<reqwest::Error> for SubgraphError {
+    fn from(err: reqwest::Error) -> Self {
+        SubgraphError::Transport(err)
+    }
+}
+
+// Why a pool could not be explored: a failed query is worth retrying, invalid data is not
+enum ExploreError {
+    Subgraph(SubgraphError),
+    InvalidData(FieldError),
+}
+
+impl From<SubgraphError> for ExploreError {
+    fn from(err: SubgraphError) -> Self {
+        ExploreError::Subgraph(err)
+    }
+}
+
+impl From<FieldError> for ExploreError {
+    fn from(err: FieldError) -> Self {
+        ExploreError::InvalidData(err)
+    }
+}
+
+// A pool found by the crawl, with everything that is written for it once the crawl is done
+struct ExploredPool {
+    pool: Pool,
+    tokens: [Token; 2],
+    ticks: Vec<Tick>,
+}
+
+// State shared by the explorer workers. Tokens are queued along with their depth, the number of
+// pools between them and a root token, and each one is explored once unless a shorter path to it
+// turns up later. The queue is unbounded since the workers feed it themselves, and the visited set
+// already bounds it to about one entry per token.
+struct Crawl {
+    // Taken once the frontier is empty, which closes the queue and stops the workers
+    queue: Mutex<Option<UnboundedSender<(String, u32)>>>,
+    // Tokens queued or being explored
+    pending: AtomicUsize,
+    // Shortest depth each token was queued at
+    visited_tokens: Mutex<HashMap<String, u32>>,
+    // Hops of the pools claimed by a worker, released again if their ticks could not be fetched
+    claimed_pools: Mutex<HashMap<String, u32>>,
+    failed_attempts: Mutex<HashMap<String, u32>>,
+    explored_pools: Mutex<Vec<ExploredPool>>,
+    // Tokens flagged as not freely swappable, never crawled to
+    flagged_tokens: HashSet<String>,
+}
+
+impl Crawl {
+    fn new(queue: UnboundedSender<(String, u32)>, flagged_tokens: HashSet<String>) -> Self {
+        Self {
+            queue: Mutex::new(Some(queue)),
+            pending: AtomicUsize::new(0),
+            visited_tokens: Mutex::new(HashMap::new()),
+            claimed_pools: Mutex::new(HashMap::new()),
+            failed_attempts: Mutex::new(HashMap::new()),
+            explored_pools: Mutex::new(vec![]),
+            flagged_tokens,
         }
+    }
 
-        for handle in handles {
-            match handle.await {
-                Ok(_) => (),
-                Err(err) => error!(error = err.to_string(), "Error during pool processing"),
+    // Queues `token` unless it was already visited at the same or a smaller depth
+    fn visit(&self, token: &str, depth: u32) {
+        {
+            let mut visited_tokens = self.visited_tokens.lock().unwrap();
+            match visited_tokens.get(token) {
+                Some(&visited_depth) if visited_depth <= depth => return,
+                _ => visited_tokens.insert(token.to_string(), depth),
             };
         }
+        self.push(token, depth);
+    }
+
+    fn push(&self, token: &str, depth: u32) {
+        self.pending.fetch_add(1, Ordering::SeqCst);
+        if let Some(queue) = self.queue.lock().unwrap().as_ref() {
+            let _ = queue.send((token.to_string(), depth));
+        }
+    }
+
+    // Marks a token as explored, closing the queue when it was the last one pending. Workers queue
+    // the tokens they find before finishing their own, so the count only drops to zero once the
+    // whole frontier is explored.
+    fn finish(&self) {
+        if self.pending.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.queue.lock().unwrap().take();
+        }
+    }
+
+    // Claims a pool for exploration, returning false if it was already claimed. A pool reached
+    // again over a shorter path keeps the smaller hop count.
+    fn claim_pool(&self, pool_id: &str, hops: u32) -> bool {
+        let mut claimed_pools = self.claimed_pools.lock().unwrap();
+        match claimed_pools.get_mut(pool_id) {
+            Some(claimed_hops) => {
+                *claimed_hops = (*claimed_hops).min(hops);
+                false
+            }
+            None => {
+                claimed_pools.insert(pool_id.to_string(), hops);
+                true
+            }
+        }
+    }
+
+    // Puts a token whose pools could not be fetched back on the queue, until it has failed
+    // `MAX_TOKEN_ATTEMPTS` times
+    fn requeue(&self, token: &str, depth: u32) {
+        let attempts = {
+            let mut failed_attempts = self.failed_attempts.lock().unwrap();
+            let attempts = failed_attempts.entry(token.to_string()).or_insert(0);
+            *attempts += 1;
+            *attempts
+        };
+        if attempts < MAX_TOKEN_ATTEMPTS {
+            self.push(token, depth);
+        } else {
+            error!(
+                token_address = token,
+                attempts, "[Explorer] Giving up on token"
+            );
+        }
+    }
+
+    fn n_failed_tokens(&self) -> usize {
+        self.failed_attempts
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|&&attempts| attempts >= MAX_TOKEN_ATTEMPTS)
+            .count()
+    }
+
+    // Explored pools with the fewest hops they were reached at
+    fn take_explored_pools(&self) -> Vec<ExploredPool> {
+        let claimed_pools = self.claimed_pools.lock().unwrap();
+        let mut explored_pools = std::mem::take(&mut *self.explored_pools.lock().unwrap());
+        for explored_pool in &mut explored_pools {
+            explored_pool.pool.hops = claimed_pools
+                .get(&explored_pool.pool.id)
+                .map(|&hops| hops as i32);
+        }
+        explored_pools
     }
 }
 
-pub async fn fetch_pools_for_token(
-    token_address: &str,
-    n_pools: Option<i64>,
-    min_tvl: Option<String>,
-) -> Vec<pools_for_token::poolFields> {
-    let query_vars = pools_for_token::Variables {
-        token_address: token_address.to_string(),
-        n_pools: n_pools.unwrap_or(1000),
-        min_tvl: min_tvl.unwrap_or_else(|| "1000".to_string()),
+// Lower bounds a pool must clear for the explorer to follow it
+pub struct PoolFilter {
+    pub min_tvl: String,
+    pub min_tvl_usd: String,
+    pub min_liquidity: String,
+}
+
+impl PoolFilter {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            min_tvl: config.min_tvl.clone(),
+            min_tvl_usd: config.min_tvl_usd.clone(),
+            min_liquidity: config.min_liquidity.clone(),
+        }
+    }
+}
+
+pub async fn find_and_update_all_pools(config: &Config) {
+    let db_pool = db_connection().await;
+    let block_number = match config.block {
+        Some(block_number) => block_number,
+        None => fetch_indexed_block(&config.subgraph_url)
+            .await
+            .expect("Failed to fetch the subgraph's indexed block"),
     };
+    info!(
+        block_number,
+        max_hops = config.max_hops,
+        "[Explorer] Snapshotting pools"
+    );
+    let started_at = Utc::now();
 
-    let data = match query(query_vars).await {
-        Ok(res) => res.data,
+    let (queue, receiver) = mpsc::unbounded_channel();
+    let receiver = Arc::new(tokio::sync::Mutex::new(receiver));
+    let flagged_tokens = TokenFlag::flagged_ids(&db_pool)
+        .await
+        .expect("Failed to fetch token flags")
+        .into_iter()
+        .collect();
+    let crawl = Arc::new(Crawl::new(queue, flagged_tokens));
+    for root_token in &config.root_tokens {
+        crawl.visit(root_token, 0);
+    }
+    if crawl.pending.load(Ordering::SeqCst) == 0 {
+        crawl.queue.lock().unwrap().take();
+    }
+
+    let mut handles: Vec<JoinHandle<()>> = vec![];
+    for _ in 0..config.workers.max(1) {
+        let crawl = crawl.clone();
+        let receiver = receiver.clone();
+        let config = config.clone();
+
+        handles.push(tokio::spawn(async move {
+            let filter = PoolFilter::from_config(&config);
+            loop {
+                let Some((addr, depth)) = receiver.lock().await.recv().await else {
+                    return;
+                };
+                explore_token(
+                    &crawl,
+                    &config,
+                    &filter,
+                    &addr,
+                    depth,
+                    block_number,
+                    started_at,
+                )
+                .await;
+                crawl.finish();
+            }
+        }));
+    }
+
+    for handle in handles {
+        match handle.await {
+            Ok(_) => (),
+            Err(err) => error!(error = err.to_string(), "Error during pool processing"),
+        };
+    }
+
+    // A token given up on leaves its branch of the graph unexplored, so its pools must not be
+    // mistaken for pools that disappeared
+    let n_failed_tokens = crawl.n_failed_tokens();
+    let complete = n_failed_tokens == 0;
+    if !complete {
+        warn!(
+            n_failed_tokens,
+            "[Explorer] Crawl is incomplete, keeping stored pools that were not reached"
+        );
+    }
+
+    let explored_pools = crawl.take_explored_pools();
+    save_explored_pools(
+        &db_pool,
+        &explored_pools,
+        started_at,
+        config.full_refresh,
+        complete,
+    )
+    .await
+    .expect("Failed to save explored pools");
+}
+
+// Explores the pools of a token `depth` pools away from a root token, queueing the tokens on their
+// other side while they are within `max_hops`
+async fn explore_token(
+    crawl: &Crawl,
+    config: &Config,
+    filter: &PoolFilter,
+    addr: &str,
+    depth: u32,
+    block_number: u64,
+    started_at: DateTime<Utc>,
+) {
+    let pools = match fetch_pools_for_token(&config.subgraph_url, addr, filter, block_number).await
+    {
+        Ok(pools) => pools,
         Err(err) => {
-            error!(error = err.to_string(), "[PoolQuery] Error fetching pools");
-            None
+            error!(
+                error = err.to_string(),
+                token_address = addr,
+                "[Explorer] Error fetching pools"
+            );
+            crawl.requeue(addr, depth);
+            return;
         }
     };
 
-    match data {
-        Some(mut data) => {
-            let mut resulting_pools = data.token0_pools;
-            resulting_pools.append(&mut data.token1_pools);
-            resulting_pools
+    let hops = depth + 1;
+    let follow_tokens = config.max_hops.is_none_or(|max_hops| hops < max_hops);
+    // Pools whose ticks could not be fetched are released, so the re-queued token picks them up
+    // again
+    let mut failed = false;
+    for pool in pools {
+        let next_token = if pool.token0.id == addr {
+            &pool.token1.id
+        } else {
+            &pool.token0.id
+        };
+        if !config.allows_token(next_token) || crawl.flagged_tokens.contains(next_token) {
+            continue;
+        }
+        if !crawl.claim_pool(&pool.id, hops) {
+            // Already explored, but the token behind it may now be closer to a root
+            if follow_tokens {
+                crawl.visit(next_token, hops);
+            }
+            continue;
+        }
+
+        match explore_pool(&config.subgraph_url, &pool, block_number, started_at).await {
+            Ok(explored_pool) => crawl.explored_pools.lock().unwrap().push(explored_pool),
+            Err(ExploreError::Subgraph(err)) => {
+                error!(
+                    pool_address = pool.id,
+                    error = err.to_string(),
+                    "[Explorer] Error fetching ticks"
+                );
+                crawl.claimed_pools.lock().unwrap().remove(&pool.id);
+                failed = true;
+                continue;
+            }
+            Err(ExploreError::InvalidData(err)) => {
+                error!(
+                    pool_address = pool.id,
+                    error = err.to_string(),
+                    "[Explorer] Invalid pool data"
+                );
+                continue;
+            }
+        }
+
+        if follow_tokens {
+            crawl.visit(next_token, hops);
+        }
+        info!(pool_address = pool.id, "[Explorer] Successfully processed");
+    }
+
+    if failed {
+        crawl.requeue(addr, depth);
+    }
+}
+
+// Pools holding `token_address` on either side, paging through each side with an `id_gt` cursor
+// until a page comes back short
+pub async fn fetch_pools_for_token(
+    subgraph_url: &str,
+    token_address: &str,
+    filter: &PoolFilter,
+    block_number: u64,
+) -> Result<Vec<pools_for_token::poolFields>, SubgraphError> {
+    let mut pools = vec![];
+    // Cursor of each side, `None` once that side is exhausted
+    let mut last_token0_pool_id = Some("".to_string());
+    let mut last_token1_pool_id = Some("".to_string());
+
+    while last_token0_pool_id.is_some() || last_token1_pool_id.is_some() {
+        let query_vars = pools_for_token::Variables {
+            token_address: token_address.to_string(),
+            n_token0_pools: last_token0_pool_id.as_ref().map_or(0, |_| POOLS_PAGE_SIZE),
+            n_token1_pools: last_token1_pool_id.as_ref().map_or(0, |_| POOLS_PAGE_SIZE),
+            last_token0_pool_id: last_token0_pool_id.clone().unwrap_or_default(),
+            last_token1_pool_id: last_token1_pool_id.clone().unwrap_or_default(),
+            min_tvl: filter.min_tvl.clone(),
+            min_tvl_usd: filter.min_tvl_usd.clone(),
+            min_liquidity: filter.min_liquidity.clone(),
+            block: block_number as i64,
+        };
+
+        let data =
+            query::<PoolsForToken>(subgraph_url, "[PoolQuery]", token_address, query_vars).await?;
+
+        for (cursor, mut page) in [
+            (&mut last_token0_pool_id, data.token0_pools),
+            (&mut last_token1_pool_id, data.token1_pools),
+        ] {
+            if cursor.is_none() {
+                continue;
+            }
+            *cursor = match page.last() {
+                Some(last_pool) if page.len() as i64 >= POOLS_PAGE_SIZE => {
+                    Some(last_pool.id.clone())
+                }
+                _ => None,
+            };
+            pools.append(&mut page);
         }
-        None => vec![],
     }
+
+    Ok(pools)
 }
 
-async fn query(
-    query_vars: pools_for_token::Variables,
-) -> Result<Response<pools_for_token::ResponseData>, reqwest::Error> {
-    let token_address = query_vars.token_address.clone();
+pub async fn fetch_ticks_for_pool(
+    subgraph_url: &str,
+    pool_address: &str,
+    block_number: u64,
+) -> Result<Vec<ticks_for_pool::tickFields>, SubgraphError> {
+    let mut ticks = vec![];
+    let mut last_tick_id = "".to_string();
+
+    loop {
+        let query_vars = ticks_for_pool::Variables {
+            pool_address: pool_address.to_string(),
+            n_ticks: TICKS_PAGE_SIZE,
+            last_tick_id: last_tick_id.clone(),
+            block: block_number as i64,
+        };
+
+        let mut page = query::<TicksForPool>(subgraph_url, "[TickQuery]", pool_address, query_vars)
+            .await?
+            .ticks;
+        let page_len = page.len() as i64;
+        if let Some(last_tick) = page.last() {
+            last_tick_id = last_tick.id.clone();
+        }
+        ticks.append(&mut page);
+
+        if page_len < TICKS_PAGE_SIZE {
+            return Ok(ticks);
+        }
+    }
+}
+
+// Latest block the subgraph has indexed, the most recent block it can be queried at
+pub async fn fetch_indexed_block(subgraph_url: &str) -> Result<u64, SubgraphError> {
+    let data =
+        query::<IndexedBlock>(subgraph_url, "[MetaQuery]", "", indexed_block::Variables).await?;
+    data.meta
+        .map(|meta| meta.block.number as u64)
+        .ok_or(SubgraphError::MissingData)
+}
+
+// Posts a query, retrying transport errors, rate limits and server errors with exponential backoff.
+// A response carrying GraphQL errors fails as a whole, even when it holds partial data.
+pub async fn query<Q: GraphQLQuery>(
+    subgraph_url: &str,
+    log_tag: &str,
+    address: &str,
+    query_vars: Q::Variables,
+) -> Result<Q::ResponseData, SubgraphError> {
     use std::time::Instant;
-    let now = Instant::now();
 
     let client = reqwest::Client::new();
-    let res = client
-        .post(UNISWAP_URL)
-        .json(&PoolsForToken::build_query(query_vars))
-        .send()
-        .await?;
+    let body = Q::build_query(query_vars);
+    let mut backoff = INITIAL_BACKOFF;
+    let mut attempt = 0;
+
+    loop {
+        let now = Instant::now();
+        let err = match client.post(subgraph_url).json(&body).send().await {
+            Ok(res) if res.status().is_success() => {
+                let duration = format!("{:.3?}", now.elapsed());
+                info!(duration, address, "{}", log_tag);
 
-    let duration = format!("{:.3?}", now.elapsed());
-    info!(duration, token_address, "[PoolQuery]");
+                let res: Response<Q::ResponseData> = res.json().await?;
+                return match (res.data, res.errors) {
+                    (_, Some(errors)) if !errors.is_empty() => Err(SubgraphError::GraphQL(errors)),
+                    (Some(data), _) => Ok(data),
+                    (None, _) => Err(SubgraphError::MissingData),
+                };
+            }
+            Ok(res) => {
+                let status = res.status();
+                let text = res.text().await.unwrap_or_default();
+                if status != StatusCode::TOO_MANY_REQUESTS && !status.is_server_error() {
+                    return Err(SubgraphError::Status(status, text));
+                }
+                SubgraphError::Status(status, text)
+            }
+            Err(err) => SubgraphError::Transport(err),
+        };
 
-    res.json().await
+        if attempt >= MAX_QUERY_RETRIES {
+            return Err(err);
+        }
+        attempt += 1;
+        warn!(
+            error = err.to_string(),
+            address,
+            attempt,
+            backoff_ms = backoff.as_millis() as u64,
+            "{} Query failed, retrying",
+            log_tag
+        );
+        tokio::time::sleep(backoff).await;
+        backoff *= 2;
+    }
 }
 
-async fn save_pool_data(
-    db_pool: &sqlx::Pool<sqlx::Postgres>,
+// Parses a pool from the subgraph and fetches its ticks, marking it as seen by this refresh
+async fn explore_pool(
+    subgraph_url: &str,
     gql_pool: &pools_for_token::poolFields,
-) {
-    let pool: Pool = gql_pool.into();
+    block_number: u64,
+    seen_at: DateTime<Utc>,
+) -> Result<ExploredPool, ExploreError> {
+    let mut pool = Pool::try_from(gql_pool)?;
+    pool.block_number = Some(block_number as i64);
+    pool.last_seen_at = Some(seen_at);
 
-    if pool.token0(db_pool).await.is_err() {
+    let tokens = [
         Token {
             id: gql_pool.token0.id.clone(),
-            symbol: gql_pool.token0.id.clone(),
-            decimals: gql_pool.token0.decimals.clone(),
-        }
-        .save(db_pool)
-        .await
-        .expect("Failed to save token0");
-    }
-
-    if pool.token1(db_pool).await.is_err() {
+            symbol: gql_pool.token0.symbol.clone(),
+            name: Some(gql_pool.token0.name.clone()),
+            decimals: column::parse("decimals", &gql_pool.token0.decimals)?,
+        },
         Token {
             id: gql_pool.token1.id.clone(),
-            symbol: gql_pool.token1.id.clone(),
-            decimals: gql_pool.token1.decimals.clone(),
+            symbol: gql_pool.token1.symbol.clone(),
+            name: Some(gql_pool.token1.name.clone()),
+            decimals: column::parse("decimals", &gql_pool.token1.decimals)?,
+        },
+    ];
+
+    let ticks = fetch_ticks_for_pool(subgraph_url, &pool.id, block_number)
+        .await?
+        .iter()
+        .map(|gql_tick| Tick::from_gql(&pool.id, gql_tick))
+        .collect::<Result<_, _>>()?;
+
+    Ok(ExploredPool {
+        pool,
+        tokens,
+        ticks,
+    })
+}
+
+// Writes the crawl in a single transaction, so readers see either the previous or the new snapshot.
+// Pools are upserted with their balances kept, unless `full_refresh` wipes all pool data first, and
+// previously explored pools the crawl did not reach are marked inactive. An incomplete crawl neither wipes nor
+// deactivates anything.
+async fn save_explored_pools(
+    db_pool: &sqlx::Pool<sqlx::Postgres>,
+    explored_pools: &[ExploredPool],
+    started_at: DateTime<Utc>,
+    full_refresh: bool,
+    complete: bool,
+) -> Result<(), sqlx::Error> {
+    let mut tx = db_pool.begin().await?;
+    if full_refresh && complete {
+        clear_pool_data(&mut tx).await?;
+    }
+
+    for explored_pool in explored_pools {
+        for token in &explored_pool.tokens {
+            token.upsert(&mut tx).await?;
         }
-        .save(db_pool)
-        .await
-        .expect("Failed to save token0");
+        explored_pool.pool.upsert(&mut tx).await?;
+        Tick::replace_for_pool(&mut tx, &explored_pool.pool.id, &explored_pool.ticks).await?;
     }
 
-    pool.save(db_pool).await.expect("Failed to save pool");
+    let mut n_deactivated = 0;
+    if complete {
+        n_deactivated = deactivate_unseen_pools(&mut tx, started_at).await?;
+    }
+    tx.commit().await?;
+
+    info!(
+        n_pools = explored_pools.len(),
+        n_deactivated, "[Explorer] Saved pools"
+    );
+    Ok(())
 }
 
-async fn clear_pool_data(db_pool: &sqlx::Pool<sqlx::Postgres>) {
-    sqlx::query!("DELETE FROM pools")
-        .execute(db_pool)
-        .await
-        .expect("Failed to clear pools");
+// Marks inactive the explored pools not seen since `started_at`, returning how many. Pools stored by
+// discovery have no hops and are left alone, as the crawl is not expected to reach them.
+async fn deactivate_unseen_pools(
+    tx: &mut Transaction<'_, Postgres>,
+    started_at: DateTime<Utc>,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        "UPDATE pools SET active = FALSE
+        WHERE active AND hops IS NOT NULL AND (last_seen_at IS NULL OR last_seen_at < $1)",
+        started_at
+    )
+    .execute(&mut **tx)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+async fn clear_pool_data(tx: &mut Transaction<'_, Postgres>) -> Result<(), sqlx::Error> {
+    sqlx::query!("DELETE FROM ticks").execute(&mut **tx).await?;
+    sqlx::query!("DELETE FROM pools").execute(&mut **tx).await?;
     sqlx::query!("DELETE FROM tokens")
-        .execute(db_pool)
-        .await
-        .expect("Failed to clear tokens");
+        .execute(&mut **tx)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use bigdecimal::BigDecimal;
+    use chrono::Duration;
+    use ethers_core::types::U256;
+
+    use super::*;
+
+    fn pool(id: &str, token_id: &str, hops: Option<i32>, last_seen_at: DateTime<Utc>) -> Pool {
+        Pool {
+            id: id.to_string(),
+            token0_id: token_id.to_string(),
+            token1_id: token_id.to_string(),
+            token0_price: BigDecimal::from(1),
+            token1_price: BigDecimal::from(1),
+            total_value_locked_token0: BigDecimal::from(0),
+            total_value_locked_token1: BigDecimal::from(0),
+            liquidity: U256::zero(),
+            fee_tier: 500,
+            token0_balance: None,
+            token1_balance: None,
+            sqrt_price: None,
+            tick: None,
+            block_number: None,
+            active: true,
+            last_seen_at: Some(last_seen_at),
+            hops,
+        }
+    }
+
+    #[tokio::test]
+    async fn deactivation_keeps_discovered_pools() {
+        let db_pool = db_connection().await;
+        // Rolled back when dropped, leaving the database untouched
+        let mut tx = db_pool.begin().await.unwrap();
+
+        let token = Token {
+            id: "0x00000000000000000000000000000000000000e1".to_string(),
+            symbol: "TEST".to_string(),
+            name: None,
+            decimals: 18,
+        };
+        token.upsert(&mut tx).await.unwrap();
+        let started_at = Utc::now();
+        let stale = started_at - Duration::hours(1);
+        let explored = pool(
+            "0x00000000000000000000000000000000000000e2",
+            &token.id,
+            Some(1),
+            stale,
+        );
+        let discovered = pool(
+            "0x00000000000000000000000000000000000000e3",
+            &token.id,
+            None,
+            stale,
+        );
+        let seen = pool(
+            "0x00000000000000000000000000000000000000e4",
+            &token.id,
+            Some(2),
+            started_at,
+        );
+        for pool in [&explored, &discovered, &seen] {
+            pool.upsert(&mut tx).await.unwrap();
+        }
+
+        deactivate_unseen_pools(&mut tx, started_at).await.unwrap();
+
+        for (pool, active) in [(explored, false), (discovered, true), (seen, true)] {
+            let row = sqlx::query!("SELECT active FROM pools WHERE id = $1", pool.id)
+                .fetch_one(&mut *tx)
+                .await
+                .unwrap();
+            assert_eq!(row.active, active, "pool {}", pool.id);
+        }
+    }
 }
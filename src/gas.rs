@@ -0,0 +1,84 @@
+use ethers_core::types::U256;
+use tracing::{error, warn};
+
+use crate::config::Config;
+
+// Decimals of ether, gas costs are paid in wei
+pub const NATIVE_DECIMALS: u32 = 18;
+
+// Gas of the transaction itself and of the router call around the swaps
+const BASE_GAS: u64 = 60_000;
+// Gas of one hop of an exact input swap that stays within the current tick
+const SWAP_GAS: u64 = 75_000;
+// Extra gas of every initialized tick a swap crosses
+const TICK_CROSS_GAS: u64 = 22_000;
+
+// Fees per gas in wei
+#[derive(Clone, Copy, Debug, Default)]
+pub struct GasPrice {
+    pub base_fee: U256,
+    pub priority_fee: U256,
+}
+
+impl GasPrice {
+    // Cost in wei of `gas_used`
+    pub fn cost(&self, gas_used: u64) -> U256 {
+        (self.base_fee + self.priority_fee) * gas_used
+    }
+}
+
+// Gas used by a cycle of `n_hops` swaps crossing `n_ticks_crossed` initialized ticks in total
+pub fn estimate_gas(n_hops: usize, n_ticks_crossed: usize) -> u64 {
+    BASE_GAS + SWAP_GAS * n_hops as u64 + TICK_CROSS_GAS * n_ticks_crossed as u64
+}
+
+// Gas price from the configured fees, with the ones left unset fetched from the node. The base fee
+// is read at `block_number`, the latest block when unset, and the priority fee is the node's current
+// suggestion. Fees that cannot be known are taken as zero.
+pub async fn fetch_gas_price(config: &Config, block_number: Option<u64>) -> GasPrice {
+    load_gas_price(config, block_number, true).await
+}
+
+// Gas price at a past block, for replays. Nodes only suggest a priority fee for now, so it is the
+// configured one or zero, leaving the block's base fee as the only fee fetched.
+pub async fn fetch_historical_gas_price(config: &Config, block_number: u64) -> GasPrice {
+    load_gas_price(config, Some(block_number), false).await
+}
+
+async fn load_gas_price(
+    config: &Config,
+    block_number: Option<u64>,
+    fetch_priority_fee: bool,
+) -> GasPrice {
+    let mut gas_price = GasPrice {
+        base_fee: config.base_fee.unwrap_or_default(),
+        priority_fee: config.priority_fee.unwrap_or_default(),
+    };
+    if config.base_fee.is_some() && (config.priority_fee.is_some() || !fetch_priority_fee) {
+        return gas_price;
+    }
+
+    let Some(node_url) = &config.node_url else {
+        warn!("[Gas] No node set and no fees configured, taking unset fees as zero");
+        return gas_price;
+    };
+    let client = config.rpc_client(node_url);
+    if config.base_fee.is_none() {
+        match client.base_fee(block_number).await {
+            Ok(Some(base_fee)) => gas_price.base_fee = base_fee,
+            Ok(None) => warn!(block_number, "[Gas] Block has no base fee"),
+            Err(err) => error!(
+                error = err.to_string(),
+                block_number, "[Gas] Error fetching base fee"
+            ),
+        }
+    }
+    if config.priority_fee.is_none() && fetch_priority_fee {
+        match client.max_priority_fee().await {
+            Ok(priority_fee) => gas_price.priority_fee = priority_fee,
+            Err(err) => error!(error = err.to_string(), "[Gas] Error fetching priority fee"),
+        }
+    }
+
+    gas_price
+}
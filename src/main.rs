@@ -1,16 +1,56 @@
 mod balancer;
+mod config;
 mod cycler;
 mod db;
+mod discovery;
 mod explorer;
+mod gas;
 mod models;
+mod multicall;
+mod pool_reader;
+mod replay;
+mod router;
+mod rpc;
+mod simulator;
+mod token_flags;
+mod token_graph;
+mod token_metadata;
+mod v3_math;
 
-use std::env;
-
+use clap::{Parser, Subcommand};
 use tracing::Level;
 use tracing_subscriber::FmtSubscriber;
 
-const USDC_ADDRESS: &str = "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48";
-const USDC_DECIMALS: &str = "6";
+use config::Config;
+
+#[derive(Parser)]
+#[command(about = "Finds arbitrage cycles across Uniswap V3 pools")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Crawl the subgraph for every pool reachable from the root token
+    Explore(Config),
+    /// Discover pools from the factory's PoolCreated logs, without the subgraph
+    Discover(Config),
+    /// Resolve symbol, name and decimals on-chain for stored tokens missing them
+    Tokens(Config),
+    /// Fetch on-chain token balances for every stored pool
+    Balances(Config),
+    /// Read price, tick and liquidity of every stored pool from the pool contracts
+    PoolState(Config),
+    /// Flag tokens from the blacklist file and tokens failing a transfer check on the fork
+    Flags(Config),
+    /// Search stored pools for profitable cycles through the root token
+    Cycles(Config),
+    /// Replay the cycle search over historical pool states from the subgraph
+    Replay(Config),
+    /// Run explore, balances, flags and cycles in order
+    RunAll(Config),
+}
 
 fn init_logger() {
     let subscriber = FmtSubscriber::builder()
@@ -22,22 +62,26 @@ fn init_logger() {
 #[tokio::main]
 async fn main() {
     init_logger();
-    dotenv::dotenv().unwrap();
-
-    if env::var("REFRESH_DATA").unwrap() == "true" {
-        explorer::find_and_update_all_pools(USDC_ADDRESS.to_string()).await;
-    }
-
-    if env::var("FETCH_BALANCES").unwrap() == "true" {
-        balancer::find_and_update_all_balances().await;
-    }
+    dotenv::dotenv().ok();
 
-    if env::var("FIND_CYCLES").unwrap() == "true" {
-        cycler::process_cycles(models::Token {
-            id: USDC_ADDRESS.to_string(),
-            symbol: "USDC".to_string(),
-            decimals: USDC_DECIMALS.to_string(),
-        })
-        .await;
+    match Cli::parse().command {
+        Command::Explore(config) => explorer::find_and_update_all_pools(&config).await,
+        Command::Discover(config) => discovery::discover_pools_from_logs(&config).await,
+        Command::Tokens(config) => token_metadata::update_missing_token_metadata(&config).await,
+        Command::Balances(config) => balancer::find_and_update_all_balances(&config).await,
+        Command::PoolState(config) => pool_reader::update_all_pool_states(&config).await,
+        Command::Flags(config) => token_flags::update_token_flags(&config).await,
+        Command::Cycles(config) => cycler::process_cycles(&config).await,
+        Command::Replay(config) => replay::replay_cycles(&config).await,
+        Command::RunAll(config) => {
+            // Checked before the crawl, the cycle simulations on the fork need a funded sender
+            if config.fork_url.is_some() {
+                config.simulation_from();
+            }
+            explorer::find_and_update_all_pools(&config).await;
+            balancer::find_and_update_all_balances(&config).await;
+            token_flags::update_token_flags(&config).await;
+            cycler::process_cycles(&config).await;
+        }
     }
 }
@@ -0,0 +1,114 @@
+use std::{error::Error, fmt, str::FromStr};
+
+use bigdecimal::{BigDecimal, ToPrimitive};
+use ethers_core::types::U256;
+use sqlx::{postgres::PgRow, Row};
+
+// A value that could not be converted into the type of the field it was meant for
+#[derive(Debug)]
+pub struct FieldError {
+    pub field: &'static str,
+    pub value: String,
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid value for {}: {:?}", self.field, self.value)
+    }
+}
+
+impl Error for FieldError {}
+
+impl FieldError {
+    fn into_column_error(self) -> sqlx::Error {
+        sqlx::Error::ColumnDecode {
+            index: self.field.to_string(),
+            source: Box::new(self),
+        }
+    }
+}
+
+pub fn parse<T: FromStr>(field: &'static str, value: &str) -> Result<T, FieldError> {
+    value.parse().map_err(|_| FieldError {
+        field,
+        value: value.to_string(),
+    })
+}
+
+pub fn parse_u256(field: &'static str, value: &str) -> Result<U256, FieldError> {
+    U256::from_dec_str(value).map_err(|_| FieldError {
+        field,
+        value: value.to_string(),
+    })
+}
+
+pub fn decimal_to_u256(field: &'static str, value: &BigDecimal) -> Result<U256, FieldError> {
+    if !value.is_integer() || value < &BigDecimal::from(0) {
+        return Err(FieldError {
+            field,
+            value: value.to_string(),
+        });
+    }
+
+    parse_u256(field, &value.with_scale(0).to_string())
+}
+
+pub fn u256_to_decimal(value: U256) -> BigDecimal {
+    BigDecimal::from_str(&value.to_string()).unwrap()
+}
+
+pub fn get_u256(row: &PgRow, column: &'static str) -> Result<U256, sqlx::Error> {
+    let value: BigDecimal = row.try_get(column)?;
+    decimal_to_u256(column, &value).map_err(FieldError::into_column_error)
+}
+
+pub fn get_optional_u256(row: &PgRow, column: &'static str) -> Result<Option<U256>, sqlx::Error> {
+    let value: Option<BigDecimal> = row.try_get(column)?;
+    value
+        .map(|value| decimal_to_u256(column, &value).map_err(FieldError::into_column_error))
+        .transpose()
+}
+
+pub fn get_i128(row: &PgRow, column: &'static str) -> Result<i128, sqlx::Error> {
+    let value: BigDecimal = row.try_get(column)?;
+    value
+        .to_i128()
+        .filter(|_| value.is_integer())
+        .ok_or_else(|| {
+            FieldError {
+                field: column,
+                value: value.to_string(),
+            }
+            .into_column_error()
+        })
+}
+
+pub fn get_u128(row: &PgRow, column: &'static str) -> Result<u128, sqlx::Error> {
+    let value: BigDecimal = row.try_get(column)?;
+    value
+        .to_u128()
+        .filter(|_| value.is_integer())
+        .ok_or_else(|| {
+            FieldError {
+                field: column,
+                value: value.to_string(),
+            }
+            .into_column_error()
+        })
+}
+
+// Reads an integer column into a narrower or unsigned type, failing if the stored value does not fit
+pub fn get_converted<S, T>(row: &PgRow, column: &'static str) -> Result<T, sqlx::Error>
+where
+    S: for<'r> sqlx::Decode<'r, sqlx::Postgres> + sqlx::Type<sqlx::Postgres> + ToString + Copy,
+    T: TryFrom<S>,
+{
+    let value: S = row.try_get(column)?;
+    T::try_from(value).map_err(|_| {
+        FieldError {
+            field: column,
+            value: value.to_string(),
+        }
+        .into_column_error()
+    })
+}
@@ -0,0 +1,107 @@
+use async_trait::async_trait;
+use bigdecimal::BigDecimal;
+use chrono::{DateTime, Utc};
+use sqlx::{query, FromRow, Postgres};
+
+use super::Model;
+
+// A cycle found by one run of the cycler, along with its projected profit at the optimal input.
+// The gas columns are unset for cycles saved before gas costs were estimated.
+#[derive(Clone, FromRow)]
+pub struct Cycle {
+    pub id: String,
+    pub root_token_id: String,
+    pub router_path: Vec<String>,
+    pub input_amount: BigDecimal,
+    pub expected_output: BigDecimal,
+    pub projected_profit: BigDecimal,
+    pub quote_profit: BigDecimal,
+    pub gas_used: Option<i64>,
+    pub gas_cost: Option<BigDecimal>,
+    pub net_profit: Option<BigDecimal>,
+    pub quote_net_profit: Option<BigDecimal>,
+    pub block_number: Option<i64>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[async_trait]
+impl Model for Cycle {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn table_name() -> String {
+        "cycles".to_string()
+    }
+
+    async fn create<'a>(&'a self, db_pool: &sqlx::Pool<Postgres>) -> Result<&'a Self, sqlx::Error> {
+        query!(
+            "INSERT INTO cycles (
+                id,
+                root_token_id,
+                router_path,
+                input_amount,
+                expected_output,
+                projected_profit,
+                quote_profit,
+                gas_used,
+                gas_cost,
+                net_profit,
+                quote_net_profit,
+                block_number,
+                created_at
+            ) values ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)",
+            self.id,
+            self.root_token_id,
+            &self.router_path,
+            self.input_amount,
+            self.expected_output,
+            self.projected_profit,
+            self.quote_profit,
+            self.gas_used,
+            self.gas_cost,
+            self.net_profit,
+            self.quote_net_profit,
+            self.block_number,
+            self.created_at,
+        )
+        .execute(db_pool)
+        .await?;
+        Ok(self)
+    }
+
+    async fn update<'a>(&'a self, db_pool: &sqlx::Pool<Postgres>) -> Result<&'a Self, sqlx::Error> {
+        query!(
+            "UPDATE cycles SET (
+                root_token_id,
+                router_path,
+                input_amount,
+                expected_output,
+                projected_profit,
+                quote_profit,
+                gas_used,
+                gas_cost,
+                net_profit,
+                quote_net_profit,
+                block_number,
+                created_at
+            ) = ($2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13) WHERE id = $1",
+            self.id,
+            self.root_token_id,
+            &self.router_path,
+            self.input_amount,
+            self.expected_output,
+            self.projected_profit,
+            self.quote_profit,
+            self.gas_used,
+            self.gas_cost,
+            self.net_profit,
+            self.quote_net_profit,
+            self.block_number,
+            self.created_at,
+        )
+        .execute(db_pool)
+        .await?;
+        Ok(self)
+    }
+}
@@ -0,0 +1,88 @@
+use async_trait::async_trait;
+use sqlx::{postgres::PgRow, query, FromRow, Postgres, Row};
+
+use super::{column, Model};
+
+// One swap of a stored cycle, `position` orders the legs starting from the root token
+#[derive(Clone)]
+pub struct CycleLeg {
+    pub id: String,
+    pub cycle_id: String,
+    pub position: u32,
+    pub pool_id: String,
+    pub token_in_id: String,
+    pub token_out_id: String,
+    pub fee_tier: u32,
+}
+
+impl<'r> FromRow<'r, PgRow> for CycleLeg {
+    fn from_row(row: &'r PgRow) -> Result<Self, sqlx::Error> {
+        Ok(Self {
+            id: row.try_get("id")?,
+            cycle_id: row.try_get("cycle_id")?,
+            position: column::get_converted::<i32, _>(row, "position")?,
+            pool_id: row.try_get("pool_id")?,
+            token_in_id: row.try_get("token_in_id")?,
+            token_out_id: row.try_get("token_out_id")?,
+            fee_tier: column::get_converted::<i32, _>(row, "fee_tier")?,
+        })
+    }
+}
+
+#[async_trait]
+impl Model for CycleLeg {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn table_name() -> String {
+        "cycle_legs".to_string()
+    }
+
+    async fn create<'a>(&'a self, db_pool: &sqlx::Pool<Postgres>) -> Result<&'a Self, sqlx::Error> {
+        query!(
+            "INSERT INTO cycle_legs (
+                id,
+                cycle_id,
+                position,
+                pool_id,
+                token_in_id,
+                token_out_id,
+                fee_tier
+            ) values ($1, $2, $3, $4, $5, $6, $7)",
+            self.id,
+            self.cycle_id,
+            self.position as i32,
+            self.pool_id,
+            self.token_in_id,
+            self.token_out_id,
+            self.fee_tier as i32,
+        )
+        .execute(db_pool)
+        .await?;
+        Ok(self)
+    }
+
+    async fn update<'a>(&'a self, db_pool: &sqlx::Pool<Postgres>) -> Result<&'a Self, sqlx::Error> {
+        query!(
+            "UPDATE cycle_legs SET (
+                cycle_id,
+                position,
+                pool_id,
+                token_in_id,
+                token_out_id,
+                fee_tier
+            ) = ($2, $3, $4, $5, $6, $7) WHERE id = $1",
+            self.id,
+            self.cycle_id,
+            self.position as i32,
+            self.pool_id,
+            self.token_in_id,
+            self.token_out_id,
+            self.fee_tier as i32,
+        )
+        .execute(db_pool)
+        .await?;
+        Ok(self)
+    }
+}
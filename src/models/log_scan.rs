@@ -0,0 +1,48 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::{query, FromRow, Postgres};
+
+use super::Model;
+
+// How far the logs of a contract have been scanned, keyed by contract address
+#[derive(Clone, FromRow)]
+pub struct LogScan {
+    pub id: String,
+    pub last_scanned_block: i64,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[async_trait]
+impl Model for LogScan {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn table_name() -> String {
+        "log_scans".to_string()
+    }
+
+    async fn create<'a>(&'a self, db_pool: &sqlx::Pool<Postgres>) -> Result<&'a Self, sqlx::Error> {
+        query!(
+            "INSERT INTO log_scans (id, last_scanned_block, updated_at) values ($1, $2, $3)",
+            self.id,
+            self.last_scanned_block,
+            self.updated_at,
+        )
+        .execute(db_pool)
+        .await?;
+        Ok(self)
+    }
+
+    async fn update<'a>(&'a self, db_pool: &sqlx::Pool<Postgres>) -> Result<&'a Self, sqlx::Error> {
+        query!(
+            "UPDATE log_scans SET (last_scanned_block, updated_at) = ($2, $3) WHERE id = $1",
+            self.id,
+            self.last_scanned_block,
+            self.updated_at,
+        )
+        .execute(db_pool)
+        .await?;
+        Ok(self)
+    }
+}
@@ -0,0 +1,8 @@
+use graphql_client::GraphQLQuery;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "uniswap-schema.json",
+    query_path = "queries/meta.graphql"
+)]
+pub struct IndexedBlock;
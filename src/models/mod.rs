@@ -1,12 +1,28 @@
 use async_trait::async_trait;
 
+pub mod column;
+mod cycle;
+mod cycle_leg;
+mod log_scan;
+pub mod meta_query;
 mod pool;
 pub mod pool_query;
+pub mod pool_state_query;
+mod replay_step;
+mod tick;
+pub mod tick_query;
 mod token;
+mod token_flag;
 
+pub use cycle::Cycle;
+pub use cycle_leg::CycleLeg;
+pub use log_scan::LogScan;
 pub use pool::Pool;
+pub use replay_step::ReplayStep;
 use sqlx::{postgres::PgRow, query_as, FromRow, Postgres};
+pub use tick::Tick;
 pub use token::Token;
+pub use token_flag::TokenFlag;
 
 #[async_trait]
 pub trait Model: for<'a> FromRow<'a, PgRow> + Unpin + Send {
@@ -21,9 +37,18 @@ pub trait Model: for<'a> FromRow<'a, PgRow> + Unpin + Send {
             .await
     }
 
-    async fn create(&self, db_pool: &sqlx::Pool<sqlx::Postgres>) -> Result<&Self, sqlx::Error>;
-    async fn update(&self, db_pool: &sqlx::Pool<sqlx::Postgres>) -> Result<&Self, sqlx::Error>;
-    async fn save(&self, db_pool: &sqlx::Pool<sqlx::Postgres>) -> Result<&Self, sqlx::Error> {
+    async fn create<'a>(
+        &'a self,
+        db_pool: &sqlx::Pool<sqlx::Postgres>,
+    ) -> Result<&'a Self, sqlx::Error>;
+    async fn update<'a>(
+        &'a self,
+        db_pool: &sqlx::Pool<sqlx::Postgres>,
+    ) -> Result<&'a Self, sqlx::Error>;
+    async fn save<'a>(
+        &'a self,
+        db_pool: &sqlx::Pool<sqlx::Postgres>,
+    ) -> Result<&'a Self, sqlx::Error> {
         match Self::find(db_pool, self.id()).await {
             Ok(_) => self.update(db_pool).await,
             Err(_) => self.create(db_pool).await,
@@ -1,22 +1,62 @@
 use async_trait::async_trait;
 use bigdecimal::BigDecimal;
-use sqlx::{query, FromRow, Postgres};
+use chrono::{DateTime, Utc};
+use ethers_core::types::U256;
+use sqlx::{postgres::PgRow, query, FromRow, Postgres, Row, Transaction};
 
-use super::{pool_query::pools_for_token::poolFields as GqlPoolFields, Model, Token};
+use super::{
+    column::{self, FieldError},
+    pool_query::pools_for_token::poolFields as GqlPoolFields,
+    Model, Token,
+};
+use crate::v3_math::{self, TickLiquidity};
 
-#[derive(Clone, FromRow, Eq)]
+#[derive(Clone, Eq)]
 pub struct Pool {
     pub id: String,
     pub token0_id: String,
     pub token1_id: String,
-    pub token0_price: String,
-    pub token1_price: String,
-    pub total_value_locked_token0: String,
-    pub total_value_locked_token1: String,
-    pub liquidity: String,
-    pub fee_tier: String,
-    pub token0_balance: String,
-    pub token1_balance: String,
+    pub token0_price: BigDecimal,
+    pub token1_price: BigDecimal,
+    pub total_value_locked_token0: BigDecimal,
+    pub total_value_locked_token1: BigDecimal,
+    pub liquidity: U256,
+    pub fee_tier: u32,
+    pub token0_balance: Option<U256>,
+    pub token1_balance: Option<U256>,
+    pub sqrt_price: Option<U256>,
+    pub tick: Option<i32>,
+    // Block the pool state was snapshotted at
+    pub block_number: Option<i64>,
+    // Whether the last explorer refresh still found the pool
+    pub active: bool,
+    pub last_seen_at: Option<DateTime<Utc>>,
+    // Fewest swaps from a root token through this pool, as found by the explorer
+    pub hops: Option<i32>,
+}
+
+impl<'r> FromRow<'r, PgRow> for Pool {
+    fn from_row(row: &'r PgRow) -> Result<Self, sqlx::Error> {
+        Ok(Self {
+            id: row.try_get("id")?,
+            token0_id: row.try_get("token0_id")?,
+            token1_id: row.try_get("token1_id")?,
+            token0_price: row.try_get("token0_price")?,
+            token1_price: row.try_get("token1_price")?,
+            total_value_locked_token0: row.try_get("total_value_locked_token0")?,
+            total_value_locked_token1: row.try_get("total_value_locked_token1")?,
+            liquidity: column::get_u256(row, "liquidity")?,
+            fee_tier: column::get_converted::<i32, _>(row, "fee_tier")?,
+            token0_balance: column::get_optional_u256(row, "token0_balance")?,
+            token1_balance: column::get_optional_u256(row, "token1_balance")?,
+            sqrt_price: column::get_optional_u256(row, "sqrt_price")?,
+            tick: row.try_get("tick")?,
+            block_number: row.try_get("block_number")?,
+            active: row.try_get("active")?,
+            last_seen_at: row.try_get("last_seen_at")?,
+            hops: row.try_get("hops")?,
+        })
+    }
 }
 
 #[async_trait]
@@ -29,7 +69,7 @@ impl Model for Pool {
         "pools".to_string()
     }
 
-    async fn create(&self, db_pool: &sqlx::Pool<Postgres>) -> Result<&Self, sqlx::Error> {
+    async fn create<'a>(&'a self, db_pool: &sqlx::Pool<Postgres>) -> Result<&'a Self, sqlx::Error> {
         query!(
             "INSERT INTO pools (
                 id,
@@ -42,8 +82,14 @@ impl Model for Pool {
                 liquidity,
                 fee_tier,
                 token0_balance,
-                token1_balance
-            ) values ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)",
+                token1_balance,
+                sqrt_price,
+                tick,
+                block_number,
+                active,
+                last_seen_at,
+                hops
+            ) values ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17)",
             self.id,
             self.token0_id,
             self.token1_id,
@@ -51,17 +97,23 @@ impl Model for Pool {
             self.token1_price,
             self.total_value_locked_token0,
             self.total_value_locked_token1,
-            self.liquidity,
-            self.fee_tier,
-            self.token0_balance,
-            self.token1_balance,
+            column::u256_to_decimal(self.liquidity),
+            self.fee_tier as i32,
+            self.token0_balance.map(column::u256_to_decimal),
+            self.token1_balance.map(column::u256_to_decimal),
+            self.sqrt_price.map(column::u256_to_decimal),
+            self.tick,
+            self.block_number,
+            self.active,
+            self.last_seen_at,
+            self.hops,
         )
         .execute(db_pool)
         .await?;
         Ok(self)
     }
 
-    async fn update(&self, db_pool: &sqlx::Pool<Postgres>) -> Result<&Self, sqlx::Error> {
+    async fn update<'a>(&'a self, db_pool: &sqlx::Pool<Postgres>) -> Result<&'a Self, sqlx::Error> {
         query!(
             "UPDATE pools SET (
                 token0_id,
@@ -73,8 +125,14 @@ impl Model for Pool {
                 liquidity,
                 fee_tier,
                 token0_balance,
-                token1_balance
-            ) = ($2, $3, $4, $5, $6, $7, $8, $9, $10, $11) WHERE id = $1",
+                token1_balance,
+                sqrt_price,
+                tick,
+                block_number,
+                active,
+                last_seen_at,
+                hops
+            ) = ($2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17) WHERE id = $1",
             self.id,
             self.token0_id,
             self.token1_id,
@@ -82,10 +140,16 @@ impl Model for Pool {
             self.token1_price,
             self.total_value_locked_token0,
             self.total_value_locked_token1,
-            self.liquidity,
-            self.fee_tier,
-            self.token0_balance,
-            self.token1_balance,
+            column::u256_to_decimal(self.liquidity),
+            self.fee_tier as i32,
+            self.token0_balance.map(column::u256_to_decimal),
+            self.token1_balance.map(column::u256_to_decimal),
+            self.sqrt_price.map(column::u256_to_decimal),
+            self.tick,
+            self.block_number,
+            self.active,
+            self.last_seen_at,
+            self.hops,
         )
         .execute(db_pool)
         .await?;
@@ -94,6 +158,63 @@ impl Model for Pool {
 }
 
 impl Pool {
+    // Inserts or refreshes the pool as seen by the explorer, keeping any balances already fetched
+    pub async fn upsert(&self, tx: &mut Transaction<'_, Postgres>) -> Result<(), sqlx::Error> {
+        query!(
+            "INSERT INTO pools (
+                id,
+                token0_id,
+                token1_id,
+                token0_price,
+                token1_price,
+                total_value_locked_token0,
+                total_value_locked_token1,
+                liquidity,
+                fee_tier,
+                sqrt_price,
+                tick,
+                block_number,
+                active,
+                last_seen_at,
+                hops
+            ) values ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15)
+            ON CONFLICT (id) DO UPDATE SET (
+                token0_id,
+                token1_id,
+                token0_price,
+                token1_price,
+                total_value_locked_token0,
+                total_value_locked_token1,
+                liquidity,
+                fee_tier,
+                sqrt_price,
+                tick,
+                block_number,
+                active,
+                last_seen_at,
+                hops
+            ) = ($2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15)",
+            self.id,
+            self.token0_id,
+            self.token1_id,
+            self.token0_price,
+            self.token1_price,
+            self.total_value_locked_token0,
+            self.total_value_locked_token1,
+            column::u256_to_decimal(self.liquidity),
+            self.fee_tier as i32,
+            self.sqrt_price.map(column::u256_to_decimal),
+            self.tick,
+            self.block_number,
+            self.active,
+            self.last_seen_at,
+            self.hops,
+        )
+        .execute(&mut **tx)
+        .await?;
+        Ok(())
+    }
+
     pub async fn token0(&self, db_pool: &sqlx::Pool<Postgres>) -> Result<Token, sqlx::Error> {
         Token::find(db_pool, &self.token0_id).await
     }
@@ -102,53 +223,104 @@ impl Pool {
         Token::find(db_pool, &self.token1_id).await
     }
 
-    // pub fn swap(&self, amount: &BigDecimal, zero_for_one: bool) -> BigDecimal {
-    //     let fee_bp: BigDecimal = self.fee_tier.parse().unwrap();
-    //     let mil: BigDecimal = 1_000_000.into();
-    //     let price: BigDecimal = if zero_for_one {
-    //         self.token1_price.parse().unwrap()
-    //     } else {
-    //         self.token0_price.parse().unwrap()
-    //     };
+    // Simulates an exact input swap against the pool's concentrated liquidity, stepping across
+    // the given initialized ticks (sorted by index). With no ticks the current liquidity is
+    // assumed to extend over the whole price range. Returns the amount out and how many initialized
+    // ticks were crossed, which drives the swap's gas cost.
+    pub fn swap(
+        &self,
+        amount_in: U256,
+        zero_for_one: bool,
+        ticks: &[TickLiquidity],
+    ) -> (U256, usize) {
+        let (mut sqrt_price, mut tick) = match (self.sqrt_price, self.tick) {
+            (Some(sqrt_price), Some(tick)) => (sqrt_price, tick),
+            _ => return (U256::zero(), 0),
+        };
+        let mut n_crossed = 0;
+        let mut liquidity = self.liquidity;
+        let sqrt_price_limit = if zero_for_one {
+            v3_math::min_sqrt_ratio() + 1
+        } else {
+            v3_math::max_sqrt_ratio() - 1
+        };
+
+        let mut amount_remaining = amount_in;
+        let mut amount_out = U256::zero();
+        while !amount_remaining.is_zero() && sqrt_price != sqrt_price_limit {
+            let next_tick = v3_math::next_initialized_tick(ticks, tick, zero_for_one);
+            let tick_next = match next_tick {
+                Some(next_tick) => next_tick.index.clamp(v3_math::MIN_TICK, v3_math::MAX_TICK),
+                None if zero_for_one => v3_math::MIN_TICK,
+                None => v3_math::MAX_TICK,
+            };
+            let sqrt_price_next = v3_math::get_sqrt_ratio_at_tick(tick_next);
+            let sqrt_price_target = if zero_for_one {
+                sqrt_price_next.max(sqrt_price_limit)
+            } else {
+                sqrt_price_next.min(sqrt_price_limit)
+            };
 
-    //     (amount * price) * (BigDecimal::from(1) - fee_bp / &mil)
-    // }
+            let step = v3_math::compute_swap_step(
+                sqrt_price,
+                sqrt_price_target,
+                liquidity,
+                amount_remaining,
+                self.fee_tier,
+            );
+            sqrt_price = step.sqrt_price_next;
+            amount_remaining -= step.amount_in + step.fee_amount;
+            amount_out += step.amount_out;
+
+            if sqrt_price != sqrt_price_next {
+                continue;
+            }
+            match next_tick {
+                Some(next_tick) => {
+                    let liquidity_net = if zero_for_one {
+                        -next_tick.liquidity_net
+                    } else {
+                        next_tick.liquidity_net
+                    };
+                    let liquidity_delta = U256::from(liquidity_net.unsigned_abs());
+                    liquidity = if liquidity_net < 0 {
+                        liquidity.saturating_sub(liquidity_delta)
+                    } else {
+                        liquidity + liquidity_delta
+                    };
+                    tick = if zero_for_one {
+                        tick_next - 1
+                    } else {
+                        tick_next
+                    };
+                    n_crossed += 1;
+                }
+                None => break,
+            }
+        }
+
+        (amount_out, n_crossed)
+    }
 
     pub fn is_token_0(&self, token_id: &str) -> bool {
         token_id == self.token0_id
     }
 
-    pub fn fee_price_for(&self, token_id: &str) -> BigDecimal {
-        let mil: BigDecimal = 1_000_000.into();
-        let fee_bp: BigDecimal = self.fee_tier.parse().unwrap();
-        let fee_percentage = BigDecimal::from(1) - fee_bp / &mil;
-        let base_price: BigDecimal = if token_id == self.token0_id {
-            self.token1_price.parse().unwrap()
+    // Spot price of `token_id` in units of the pool's other token
+    pub fn price_for(&self, token_id: &str) -> &BigDecimal {
+        if token_id == self.token0_id {
+            &self.token1_price
         } else {
-            self.token0_price.parse().unwrap()
-        };
-
-        base_price * fee_percentage
-    }
-
-    pub async fn token0_balance(&self, db_pool: &sqlx::Pool<Postgres>) -> Result<BigDecimal, sqlx::Error> {
-        if self.token0_balance.is_empty() {
-            return Ok(BigDecimal::from(0));
+            &self.token0_price
         }
-
-        let token0_balance: BigDecimal = self.token0_balance.parse().unwrap();
-        let token0_decimals: u32 = self.token0(db_pool).await?.decimals.parse().unwrap();
-        Ok(token0_balance / (10_i64.pow(token0_decimals)))
     }
 
-    pub async fn token1_balance(&self, db_pool: &sqlx::Pool<Postgres>) -> Result<BigDecimal, sqlx::Error> {
-        if self.token1_balance.is_empty() {
-            return Ok(BigDecimal::from(0));
-        }
+    pub fn fee_price_for(&self, token_id: &str) -> BigDecimal {
+        let mil: BigDecimal = 1_000_000.into();
+        let fee_bp = BigDecimal::from(self.fee_tier);
+        let fee_percentage = BigDecimal::from(1) - fee_bp / &mil;
 
-        let token1_balance: BigDecimal = self.token1_balance.parse().unwrap();
-        let token1_decimals: u32 = self.token1(db_pool).await?.decimals.parse().unwrap();
-        Ok(token1_balance / (10_i64.pow(token1_decimals)))
+        self.price_for(token_id) * fee_percentage
     }
 }
 
@@ -158,20 +330,114 @@ impl PartialEq for Pool {
     }
 }
 
-impl From<&GqlPoolFields> for Pool {
-    fn from(gpf: &GqlPoolFields) -> Self {
-        Self {
+impl TryFrom<&GqlPoolFields> for Pool {
+    type Error = FieldError;
+
+    fn try_from(gpf: &GqlPoolFields) -> Result<Self, Self::Error> {
+        Ok(Self {
             id: gpf.id.clone(),
             token0_id: gpf.token0.id.clone(),
             token1_id: gpf.token1.id.clone(),
-            token0_price: gpf.token0_price.clone(),
-            token1_price: gpf.token1_price.clone(),
-            total_value_locked_token0: gpf.total_value_locked_token0.clone(),
-            total_value_locked_token1: gpf.total_value_locked_token1.clone(),
-            liquidity: gpf.liquidity.clone(),
-            fee_tier: gpf.fee_tier.clone(),
-            token0_balance: "".to_string(),
-            token1_balance: "".to_string(),
+            token0_price: column::parse("token0_price", &gpf.token0_price)?,
+            token1_price: column::parse("token1_price", &gpf.token1_price)?,
+            total_value_locked_token0: column::parse(
+                "total_value_locked_token0",
+                &gpf.total_value_locked_token0,
+            )?,
+            total_value_locked_token1: column::parse(
+                "total_value_locked_token1",
+                &gpf.total_value_locked_token1,
+            )?,
+            liquidity: column::parse_u256("liquidity", &gpf.liquidity)?,
+            fee_tier: column::parse("fee_tier", &gpf.fee_tier)?,
+            token0_balance: None,
+            token1_balance: None,
+            sqrt_price: Some(column::parse_u256("sqrt_price", &gpf.sqrt_price)?),
+            tick: gpf
+                .tick
+                .as_deref()
+                .map(|tick| column::parse("tick", tick))
+                .transpose()?,
+            block_number: None,
+            active: true,
+            last_seen_at: None,
+            hops: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIQUIDITY: i128 = 1_000_000_000_000_000_000;
+
+    // Pool at tick 0 with no liquidity in range
+    fn pool_at_tick_zero() -> Pool {
+        Pool {
+            id: "0x0000000000000000000000000000000000000001".to_string(),
+            token0_id: "0x0000000000000000000000000000000000000002".to_string(),
+            token1_id: "0x0000000000000000000000000000000000000003".to_string(),
+            token0_price: BigDecimal::from(1),
+            token1_price: BigDecimal::from(1),
+            total_value_locked_token0: BigDecimal::from(0),
+            total_value_locked_token1: BigDecimal::from(0),
+            liquidity: U256::zero(),
+            fee_tier: 3000,
+            token0_balance: None,
+            token1_balance: None,
+            sqrt_price: Some(v3_math::get_sqrt_ratio_at_tick(0)),
+            tick: Some(0),
+            block_number: None,
+            active: true,
+            last_seen_at: None,
+            hops: None,
         }
     }
+
+    // Single position between `lower` and `upper`
+    fn position(lower: i32, upper: i32) -> Vec<TickLiquidity> {
+        vec![
+            TickLiquidity {
+                index: lower,
+                liquidity_net: LIQUIDITY,
+            },
+            TickLiquidity {
+                index: upper,
+                liquidity_net: -LIQUIDITY,
+            },
+        ]
+    }
+
+    // The output only comes from the position, so liquidity must be added when crossing into it
+    // and removed again when crossing out of it
+    #[test]
+    fn swap_zero_for_one_crosses_ticks() {
+        let (amount_out, n_crossed) =
+            pool_at_tick_zero().swap(U256::exp10(30), true, &position(-120, -60));
+
+        let expected_out = v3_math::get_amount1_delta(
+            v3_math::get_sqrt_ratio_at_tick(-120),
+            v3_math::get_sqrt_ratio_at_tick(-60),
+            U256::from(LIQUIDITY),
+            false,
+        );
+        assert_eq!(amount_out, expected_out);
+        assert_eq!(n_crossed, 2);
+    }
+
+    #[test]
+    fn swap_one_for_zero_crosses_ticks() {
+        let (amount_out, n_crossed) =
+            pool_at_tick_zero().swap(U256::exp10(30), false, &position(60, 120));
+
+        let expected_out = v3_math::get_amount0_delta(
+            v3_math::get_sqrt_ratio_at_tick(60),
+            v3_math::get_sqrt_ratio_at_tick(120),
+            U256::from(LIQUIDITY),
+            false,
+        );
+        assert_eq!(amount_out, expected_out);
+        assert_eq!(n_crossed, 2);
+    }
 }
@@ -0,0 +1,11 @@
+use graphql_client::GraphQLQuery;
+
+type BigInt = String;
+type BigDecimal = String;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "uniswap-schema.json",
+    query_path = "queries/pool_states.graphql"
+)]
+pub struct PoolStates;
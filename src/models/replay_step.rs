@@ -0,0 +1,98 @@
+use async_trait::async_trait;
+use bigdecimal::BigDecimal;
+use chrono::{DateTime, Utc};
+use sqlx::{query, FromRow, Postgres};
+
+use super::Model;
+
+// Cycles found at one block of a historical replay, all steps of a replay sharing `replay_id`.
+// Only the most profitable cycles after gas are kept, in order. Without ticks (`used_ticks` unset)
+// the swap amounts assume unbounded liquidity, so profits are only comparable between steps.
+#[derive(Clone, FromRow)]
+pub struct ReplayStep {
+    pub id: String,
+    pub replay_id: String,
+    pub block_number: i64,
+    pub max_depth: i32,
+    pub min_quote_amount: i64,
+    pub n_cycles: i32,
+    pub used_ticks: bool,
+    pub top_quote_profits: Vec<BigDecimal>,
+    pub top_quote_net_profits: Vec<BigDecimal>,
+    pub top_symbol_paths: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[async_trait]
+impl Model for ReplayStep {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn table_name() -> String {
+        "replay_steps".to_string()
+    }
+
+    async fn create<'a>(&'a self, db_pool: &sqlx::Pool<Postgres>) -> Result<&'a Self, sqlx::Error> {
+        query!(
+            "INSERT INTO replay_steps (
+                id,
+                replay_id,
+                block_number,
+                max_depth,
+                min_quote_amount,
+                n_cycles,
+                used_ticks,
+                top_quote_profits,
+                top_quote_net_profits,
+                top_symbol_paths,
+                created_at
+            ) values ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)",
+            self.id,
+            self.replay_id,
+            self.block_number,
+            self.max_depth,
+            self.min_quote_amount,
+            self.n_cycles,
+            self.used_ticks,
+            &self.top_quote_profits,
+            &self.top_quote_net_profits,
+            &self.top_symbol_paths,
+            self.created_at,
+        )
+        .execute(db_pool)
+        .await?;
+        Ok(self)
+    }
+
+    async fn update<'a>(&'a self, db_pool: &sqlx::Pool<Postgres>) -> Result<&'a Self, sqlx::Error> {
+        query!(
+            "UPDATE replay_steps SET (
+                replay_id,
+                block_number,
+                max_depth,
+                min_quote_amount,
+                n_cycles,
+                used_ticks,
+                top_quote_profits,
+                top_quote_net_profits,
+                top_symbol_paths,
+                created_at
+            ) = ($2, $3, $4, $5, $6, $7, $8, $9, $10, $11) WHERE id = $1",
+            self.id,
+            self.replay_id,
+            self.block_number,
+            self.max_depth,
+            self.min_quote_amount,
+            self.n_cycles,
+            self.used_ticks,
+            &self.top_quote_profits,
+            &self.top_quote_net_profits,
+            &self.top_symbol_paths,
+            self.created_at,
+        )
+        .execute(db_pool)
+        .await?;
+        Ok(self)
+    }
+}
@@ -0,0 +1,119 @@
+use async_trait::async_trait;
+use bigdecimal::BigDecimal;
+use sqlx::{postgres::PgRow, query, FromRow, Postgres, Row, Transaction};
+
+use super::{
+    column::{self, FieldError},
+    tick_query::ticks_for_pool::tickFields as GqlTickFields,
+    Model,
+};
+use crate::v3_math::TickLiquidity;
+
+#[derive(Clone)]
+pub struct Tick {
+    pub id: String,
+    pub pool_id: String,
+    pub tick_idx: i32,
+    pub liquidity_net: i128,
+    pub liquidity_gross: u128,
+}
+
+impl<'r> FromRow<'r, PgRow> for Tick {
+    fn from_row(row: &'r PgRow) -> Result<Self, sqlx::Error> {
+        Ok(Self {
+            id: row.try_get("id")?,
+            pool_id: row.try_get("pool_id")?,
+            tick_idx: row.try_get("tick_idx")?,
+            liquidity_net: column::get_i128(row, "liquidity_net")?,
+            liquidity_gross: column::get_u128(row, "liquidity_gross")?,
+        })
+    }
+}
+
+#[async_trait]
+impl Model for Tick {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn table_name() -> String {
+        "ticks".to_string()
+    }
+
+    async fn create<'a>(&'a self, db_pool: &sqlx::Pool<Postgres>) -> Result<&'a Self, sqlx::Error> {
+        query!(
+            "INSERT INTO ticks (id, pool_id, tick_idx, liquidity_net, liquidity_gross) values ($1, $2, $3, $4, $5)",
+            self.id,
+            self.pool_id,
+            self.tick_idx,
+            BigDecimal::new(self.liquidity_net.into(), 0),
+            BigDecimal::new(self.liquidity_gross.into(), 0)
+        )
+        .execute(db_pool)
+        .await?;
+        Ok(self)
+    }
+
+    async fn update<'a>(&'a self, db_pool: &sqlx::Pool<Postgres>) -> Result<&'a Self, sqlx::Error> {
+        query!(
+            "UPDATE ticks SET (pool_id, tick_idx, liquidity_net, liquidity_gross) = ($2, $3, $4, $5) WHERE id=$1",
+            self.id,
+            self.pool_id,
+            self.tick_idx,
+            BigDecimal::new(self.liquidity_net.into(), 0),
+            BigDecimal::new(self.liquidity_gross.into(), 0)
+        )
+        .execute(db_pool)
+        .await?;
+        Ok(self)
+    }
+}
+
+impl Tick {
+    // Swaps the stored initialized ticks of a pool for `ticks`
+    pub async fn replace_for_pool(
+        tx: &mut Transaction<'_, Postgres>,
+        pool_id: &str,
+        ticks: &[Tick],
+    ) -> Result<(), sqlx::Error> {
+        query!("DELETE FROM ticks WHERE pool_id = $1", pool_id)
+            .execute(&mut **tx)
+            .await?;
+        for tick in ticks {
+            query!(
+                "INSERT INTO ticks (id, pool_id, tick_idx, liquidity_net, liquidity_gross) values ($1, $2, $3, $4, $5)",
+                tick.id,
+                tick.pool_id,
+                tick.tick_idx,
+                BigDecimal::new(tick.liquidity_net.into(), 0),
+                BigDecimal::new(tick.liquidity_gross.into(), 0)
+            )
+            .execute(&mut **tx)
+            .await?;
+        }
+        Ok(())
+    }
+
+    // Initialized ticks of a pool sorted by index, in the form the swap simulator consumes
+    pub fn liquidity_ticks(ticks: &[Tick]) -> Vec<TickLiquidity> {
+        let mut liquidity_ticks: Vec<TickLiquidity> = ticks
+            .iter()
+            .map(|tick| TickLiquidity {
+                index: tick.tick_idx,
+                liquidity_net: tick.liquidity_net,
+            })
+            .collect();
+        liquidity_ticks.sort_by_key(|tick| tick.index);
+        liquidity_ticks
+    }
+
+    pub fn from_gql(pool_id: &str, gtf: &GqlTickFields) -> Result<Self, FieldError> {
+        Ok(Self {
+            id: gtf.id.clone(),
+            pool_id: pool_id.to_string(),
+            tick_idx: column::parse("tick_idx", &gtf.tick_idx)?,
+            liquidity_net: column::parse("liquidity_net", &gtf.liquidity_net)?,
+            liquidity_gross: column::parse("liquidity_gross", &gtf.liquidity_gross)?,
+        })
+    }
+}
@@ -0,0 +1,10 @@
+use graphql_client::GraphQLQuery;
+
+type BigInt = String;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "uniswap-schema.json",
+    query_path = "queries/ticks.graphql"
+)]
+pub struct TicksForPool;
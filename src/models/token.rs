@@ -1,13 +1,24 @@
 use async_trait::async_trait;
-use sqlx::{query, FromRow, Postgres};
+use sqlx::{postgres::PgRow, query, FromRow, Postgres, Row, Transaction};
 
-use super::Model;
+use super::{column, Model};
 
-#[derive(FromRow)]
 pub struct Token {
     pub id: String,
     pub symbol: String,
-    pub decimals: String,
+    pub name: Option<String>,
+    pub decimals: u8,
+}
+
+impl<'r> FromRow<'r, PgRow> for Token {
+    fn from_row(row: &'r PgRow) -> Result<Self, sqlx::Error> {
+        Ok(Self {
+            id: row.try_get("id")?,
+            symbol: row.try_get("symbol")?,
+            name: row.try_get("name")?,
+            decimals: column::get_converted::<i16, _>(row, "decimals")?,
+        })
+    }
 }
 
 #[async_trait]
@@ -20,27 +31,45 @@ impl Model for Token {
         "tokens".to_string()
     }
 
-    async fn create(&self, db_pool: &sqlx::Pool<Postgres>) -> Result<&Self, sqlx::Error> {
+    async fn create<'a>(&'a self, db_pool: &sqlx::Pool<Postgres>) -> Result<&'a Self, sqlx::Error> {
         query!(
-            "INSERT INTO tokens (id, symbol, decimals) values ($1, $2, $3)",
+            "INSERT INTO tokens (id, symbol, name, decimals) values ($1, $2, $3, $4)",
             self.id,
             self.symbol,
-            self.decimals
+            self.name,
+            self.decimals as i16
         )
         .execute(db_pool)
         .await?;
         Ok(self)
     }
 
-    async fn update(&self, db_pool: &sqlx::Pool<Postgres>) -> Result<&Self, sqlx::Error> {
+    async fn update<'a>(&'a self, db_pool: &sqlx::Pool<Postgres>) -> Result<&'a Self, sqlx::Error> {
         query!(
-            "UPDATE tokens SET (symbol, decimals) = ($2, $3)  WHERE id=$1",
+            "UPDATE tokens SET (symbol, name, decimals) = ($2, $3, $4)  WHERE id=$1",
             self.id,
             self.symbol,
-            self.decimals
+            self.name,
+            self.decimals as i16
         )
         .execute(db_pool)
         .await?;
         Ok(self)
     }
 }
+
+impl Token {
+    pub async fn upsert(&self, tx: &mut Transaction<'_, Postgres>) -> Result<(), sqlx::Error> {
+        query!(
+            "INSERT INTO tokens (id, symbol, name, decimals) values ($1, $2, $3, $4)
+            ON CONFLICT (id) DO UPDATE SET (symbol, name, decimals) = ($2, $3, $4)",
+            self.id,
+            self.symbol,
+            self.name,
+            self.decimals as i16
+        )
+        .execute(&mut **tx)
+        .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,61 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::{query, FromRow, Postgres};
+
+use super::Model;
+
+// Token the explorer and cycler stay away from, keyed by token address. `source` tells whether it
+// came from the blacklist file or from the transfer check.
+#[derive(Clone, FromRow)]
+pub struct TokenFlag {
+    pub id: String,
+    pub reason: String,
+    pub source: String,
+    pub flagged_at: DateTime<Utc>,
+}
+
+#[async_trait]
+impl Model for TokenFlag {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn table_name() -> String {
+        "token_flags".to_string()
+    }
+
+    async fn create<'a>(&'a self, db_pool: &sqlx::Pool<Postgres>) -> Result<&'a Self, sqlx::Error> {
+        query!(
+            "INSERT INTO token_flags (id, reason, source, flagged_at) values ($1, $2, $3, $4)",
+            self.id,
+            self.reason,
+            self.source,
+            self.flagged_at,
+        )
+        .execute(db_pool)
+        .await?;
+        Ok(self)
+    }
+
+    async fn update<'a>(&'a self, db_pool: &sqlx::Pool<Postgres>) -> Result<&'a Self, sqlx::Error> {
+        query!(
+            "UPDATE token_flags SET (reason, source, flagged_at) = ($2, $3, $4) WHERE id = $1",
+            self.id,
+            self.reason,
+            self.source,
+            self.flagged_at,
+        )
+        .execute(db_pool)
+        .await?;
+        Ok(self)
+    }
+}
+
+impl TokenFlag {
+    pub async fn flagged_ids(db_pool: &sqlx::Pool<Postgres>) -> Result<Vec<String>, sqlx::Error> {
+        let rows = query!("SELECT id FROM token_flags")
+            .fetch_all(db_pool)
+            .await?;
+        Ok(rows.into_iter().map(|row| row.id).collect())
+    }
+}
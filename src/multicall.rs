@@ -0,0 +1,99 @@
+use ethers_core::{
+    abi::{self, ParamType, Token},
+    types::{Address, Bytes},
+    utils,
+};
+
+const AGGREGATE3_SIGNATURE: &str = "aggregate3((address,bool,bytes)[])";
+
+// One sub-call of Multicall3.aggregate3
+pub struct Call3 {
+    pub target: Address,
+    pub allow_failure: bool,
+    pub call_data: Bytes,
+}
+
+// Outcome of a sub-call, `return_data` holds the revert data when `success` is false
+pub struct CallResult {
+    pub success: bool,
+    pub return_data: Bytes,
+}
+
+pub fn encode_aggregate3(calls: &[Call3]) -> Bytes {
+    let encoded_calls = abi::encode(&[Token::Array(
+        calls
+            .iter()
+            .map(|call| {
+                Token::Tuple(vec![
+                    Token::Address(call.target),
+                    Token::Bool(call.allow_failure),
+                    Token::Bytes(call.call_data.to_vec()),
+                ])
+            })
+            .collect(),
+    )]);
+
+    let mut calldata = utils::id(AGGREGATE3_SIGNATURE).to_vec();
+    calldata.extend(encoded_calls);
+    calldata.into()
+}
+
+// Decodes the `(bool success, bytes returnData)[]` returned by aggregate3
+pub fn decode_aggregate3(output: &[u8]) -> Result<Vec<CallResult>, abi::Error> {
+    let result_type = ParamType::Array(Box::new(ParamType::Tuple(vec![
+        ParamType::Bool,
+        ParamType::Bytes,
+    ])));
+    let results = abi::decode(&[result_type], output)?
+        .remove(0)
+        .into_array()
+        .unwrap();
+
+    Ok(results
+        .into_iter()
+        .map(|result| {
+            let mut fields = result.into_tuple().unwrap().into_iter();
+            CallResult {
+                success: fields.next().unwrap().into_bool().unwrap(),
+                return_data: fields.next().unwrap().into_bytes().unwrap().into(),
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aggregate3_calldata_starts_with_selector() {
+        let calldata = encode_aggregate3(&[Call3 {
+            target: Address::repeat_byte(1),
+            allow_failure: true,
+            call_data: Bytes::from(vec![0x70, 0xa0, 0x82, 0x31]),
+        }]);
+
+        assert_eq!(&calldata[..4], &[0x82, 0xad, 0x56, 0xcb]);
+    }
+
+    #[test]
+    fn aggregate3_output_round_trips_with_failed_call() {
+        let output = abi::encode(&[Token::Array(vec![
+            Token::Tuple(vec![Token::Bool(true), Token::Bytes(vec![0; 32])]),
+            Token::Tuple(vec![
+                Token::Bool(false),
+                Token::Bytes(vec![0x08, 0xc3, 0x79, 0xa0]),
+            ]),
+        ])]);
+        let results = decode_aggregate3(&output).unwrap();
+
+        assert_eq!(results.len(), 2);
+        assert!(results[0].success);
+        assert_eq!(results[0].return_data.to_vec(), vec![0; 32]);
+        assert!(!results[1].success);
+        assert_eq!(
+            results[1].return_data.to_vec(),
+            vec![0x08, 0xc3, 0x79, 0xa0]
+        );
+    }
+}
@@ -0,0 +1,265 @@
+use bigdecimal::{BigDecimal, Zero};
+use ethers_core::{
+    types::{Address, Bytes, I256, U256},
+    utils::id,
+};
+use num_bigint::BigInt;
+use tracing::{error, info, warn};
+
+use crate::{
+    balancer,
+    config::Config,
+    db::db_connection,
+    models::{column, Model, Pool, Token},
+    rpc::{RPCClient, Request},
+};
+
+// Pool getters read for every pool, in the order their results are decoded
+const POOL_GETTERS: [&str; 5] = ["slot0()", "liquidity()", "fee()", "token0()", "token1()"];
+
+// Pool state as read from the pool contract
+pub struct PoolState {
+    pub token0: Address,
+    pub token1: Address,
+    pub fee: u32,
+    pub liquidity: U256,
+    pub sqrt_price_x96: U256,
+    pub tick: i32,
+}
+
+impl PoolState {
+    // Price of token0 in units of token1, adjusted for decimals, what the subgraph calls
+    // `token1Price`
+    pub fn token1_price(&self, decimals0: u8, decimals1: u8) -> BigDecimal {
+        let sqrt_price = column::u256_to_decimal(self.sqrt_price_x96);
+        let q192 = BigDecimal::from(BigInt::from(1) << 192);
+        let raw_price = &sqrt_price * &sqrt_price / q192;
+
+        raw_price * BigDecimal::new(1.into(), i64::from(decimals1) - i64::from(decimals0))
+    }
+
+    // Price of token1 in units of token0, the subgraph's `token0Price`
+    pub fn token0_price(&self, decimals0: u8, decimals1: u8) -> BigDecimal {
+        let token1_price = self.token1_price(decimals0, decimals1);
+        if token1_price.is_zero() {
+            return BigDecimal::zero();
+        }
+        BigDecimal::from(1) / token1_price
+    }
+}
+
+// Refreshes price, tick and liquidity of every stored pool from the chain. With `cross_check` set
+// the stored pools are left untouched and only their divergence from the chain is logged.
+pub async fn update_all_pool_states(config: &Config) {
+    let db_pool = db_connection().await;
+    let client = config.rpc_client(config.node_url());
+    let pools = balancer::fetch_all_pools(&db_pool).await;
+    let block_number = match balancer::snapshot_block(&client, config, &pools).await {
+        Ok(block_number) => block_number,
+        Err(err) => {
+            error!(
+                error = err.to_string(),
+                "[PoolReader] Error fetching block number"
+            );
+            return;
+        }
+    };
+    info!(block_number, "[PoolReader] Reading pool states");
+
+    let pool_ids: Vec<Address> = pools
+        .iter()
+        .map(|pool| pool.id.parse().expect("Invalid pool address"))
+        .collect();
+    let states = match read_pool_states(&client, &pool_ids, block_number).await {
+        Ok(states) => states,
+        Err(err) => {
+            error!(error = err, "[PoolReader] Error reading pool states");
+            return;
+        }
+    };
+
+    for (mut pool, state) in pools.into_iter().zip(states) {
+        let state = match state {
+            Ok(state) => state,
+            Err(err) => {
+                error!(
+                    error = err,
+                    pool_id = pool.id,
+                    "[PoolReader] Error reading pool state"
+                );
+                continue;
+            }
+        };
+        let (token0, token1) = match (pool.token0(&db_pool).await, pool.token1(&db_pool).await) {
+            (Ok(token0), Ok(token1)) => (token0, token1),
+            _ => {
+                error!(pool_id = pool.id, "[PoolReader] Pool tokens not found");
+                continue;
+            }
+        };
+        if !matches_tokens(&pool, &state) {
+            error!(
+                pool_id = pool.id,
+                "[PoolReader] Stored tokens or fee differ from the pool contract, skipping"
+            );
+            continue;
+        }
+
+        let token1_price = state.token1_price(token0.decimals, token1.decimals);
+        let deviation = price_deviation(&pool.token1_price, &token1_price);
+        if deviation > config.max_price_deviation {
+            warn!(
+                pool_id = pool.id,
+                stored_price = format!("{:.8}", pool.token1_price),
+                onchain_price = format!("{:.8}", token1_price),
+                deviation = format!("{:.5}", deviation),
+                "[PoolReader] Stored price deviates from the chain"
+            );
+        }
+        if config.cross_check {
+            continue;
+        }
+
+        apply_state(&mut pool, &state, &token0, &token1, block_number);
+        if let Err(err) = pool.save(&db_pool).await {
+            error!(
+                error = err.to_string(),
+                "[PoolReader] Error saving pool state"
+            );
+        }
+    }
+}
+
+// Reads slot0, liquidity, fee and tokens of each pool at `block_number`, returning one result per
+// pool in the same order
+pub async fn read_pool_states(
+    client: &RPCClient,
+    pool_ids: &[Address],
+    block_number: u64,
+) -> Result<Vec<Result<PoolState, String>>, String> {
+    let requests: Vec<Request> = pool_ids
+        .iter()
+        .flat_map(|&pool_id| {
+            POOL_GETTERS.iter().map(move |getter| {
+                Request::eth_call(
+                    None,
+                    pool_id,
+                    Bytes::from(id(getter).to_vec()),
+                    block_number,
+                )
+            })
+        })
+        .collect();
+
+    let results = client
+        .batch::<Bytes>(requests)
+        .await
+        .map_err(|err| err.to_string())?;
+
+    Ok(results
+        .chunks(POOL_GETTERS.len())
+        .map(|pool_results| {
+            let outputs: Vec<&Bytes> = pool_results
+                .iter()
+                .map(|result| result.as_ref().map_err(|err| err.to_string()))
+                .collect::<Result<_, _>>()?;
+            decode_pool_state(&outputs)
+        })
+        .collect())
+}
+
+// Overwrites the subgraph derived swap state of `pool` with the state read from the chain
+pub fn apply_state(
+    pool: &mut Pool,
+    state: &PoolState,
+    token0: &Token,
+    token1: &Token,
+    block_number: u64,
+) {
+    pool.token0_price = state.token0_price(token0.decimals, token1.decimals);
+    pool.token1_price = state.token1_price(token0.decimals, token1.decimals);
+    pool.liquidity = state.liquidity;
+    pool.sqrt_price = Some(state.sqrt_price_x96);
+    pool.tick = Some(state.tick);
+    pool.block_number = Some(block_number as i64);
+}
+
+fn decode_pool_state(outputs: &[&Bytes]) -> Result<PoolState, String> {
+    let words: Vec<U256> = outputs
+        .iter()
+        .zip(POOL_GETTERS)
+        .map(|(output, getter)| match output.get(..32) {
+            Some(word) => Ok(U256::from_big_endian(word)),
+            None => Err(format!("{} returned {} bytes", getter, output.len())),
+        })
+        .collect::<Result<_, _>>()?;
+    let slot0 = outputs[0];
+    let tick_word = slot0
+        .get(32..64)
+        .ok_or_else(|| format!("slot0() returned {} bytes", slot0.len()))?;
+
+    Ok(PoolState {
+        sqrt_price_x96: words[0],
+        tick: I256::from_raw(U256::from_big_endian(tick_word)).as_i32(),
+        liquidity: words[1],
+        fee: words[2].low_u32(),
+        token0: Address::from_slice(&outputs[3][12..32]),
+        token1: Address::from_slice(&outputs[4][12..32]),
+    })
+}
+
+fn matches_tokens(pool: &Pool, state: &PoolState) -> bool {
+    let same_token = |stored: &str, onchain: Address| {
+        stored
+            .parse::<Address>()
+            .is_ok_and(|stored| stored == onchain)
+    };
+    same_token(&pool.token0_id, state.token0)
+        && same_token(&pool.token1_id, state.token1)
+        && pool.fee_tier == state.fee
+}
+
+// Relative difference between a stored and an on-chain price
+fn price_deviation(stored: &BigDecimal, onchain: &BigDecimal) -> BigDecimal {
+    if onchain.is_zero() {
+        return if stored.is_zero() {
+            BigDecimal::zero()
+        } else {
+            BigDecimal::from(1)
+        };
+    }
+    ((stored - onchain) / onchain).abs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prices_adjust_for_decimals() {
+        // USDC (6 decimals) / WETH (18 decimals) at 2500 USDC per WETH, 4e8 raw WETH per raw USDC
+        let state = PoolState {
+            token0: Address::zero(),
+            token1: Address::zero(),
+            fee: 500,
+            liquidity: U256::zero(),
+            sqrt_price_x96: U256::from(20_000) << 96,
+            tick: 0,
+        };
+        assert_eq!(state.token1_price(6, 18), "0.0004".parse().unwrap());
+        assert_eq!(state.token0_price(6, 18), BigDecimal::from(2500));
+    }
+
+    #[test]
+    fn zero_price_has_zero_inverse() {
+        let state = PoolState {
+            token0: Address::zero(),
+            token1: Address::zero(),
+            fee: 500,
+            liquidity: U256::zero(),
+            sqrt_price_x96: U256::zero(),
+            tick: 0,
+        };
+        assert_eq!(state.token0_price(6, 18), BigDecimal::zero());
+    }
+}
@@ -0,0 +1,222 @@
+use std::collections::HashMap;
+
+use bigdecimal::BigDecimal;
+use chrono::Utc;
+use ethers_core::types::U256;
+use tracing::{error, info};
+use uuid::Uuid;
+
+use crate::{
+    config::Config,
+    cycler,
+    db::db_connection,
+    explorer::{self, SubgraphError},
+    gas,
+    models::{
+        column::{self, FieldError},
+        pool_state_query::{pool_states, PoolStates},
+        Model, Pool, ReplayStep, Tick,
+    },
+    token_graph::{GraphToken, TokenGraph},
+};
+
+const POOL_STATES_PAGE_SIZE: usize = 1000;
+
+// Runs the cycle finder over the stored pools as they were at every `replay_step` blocks of the
+// replay range, recording how many cycles each block had and the most profitable ones. Pool states
+// come from block-pinned subgraph queries, so the stored pools only decide which pools take part.
+pub async fn replay_cycles(config: &Config) {
+    let db_pool = db_connection().await;
+    let from_block = config
+        .replay_from_block
+        .expect("--replay-from-block or REPLAY_FROM_BLOCK must be set");
+    let to_block = match config.replay_to_block {
+        Some(block_number) => block_number,
+        None => explorer::fetch_indexed_block(&config.subgraph_url)
+            .await
+            .expect("Failed to fetch the subgraph's indexed block"),
+    };
+    let graph = TokenGraph::load(&db_pool, config.cycle_max_hops)
+        .await
+        .expect("Failed to load token graph");
+    let replay_id = Uuid::new_v4().to_string();
+    info!(
+        replay_id,
+        from_block,
+        to_block,
+        n_pools = graph.pools.len(),
+        use_ticks = config.replay_ticks,
+        "[Replay] Replaying cycles"
+    );
+
+    for block_number in (from_block..=to_block).step_by(config.replay_step.max(1) as usize) {
+        let historical_graph = match load_historical_graph(&graph, config, block_number).await {
+            Ok(historical_graph) => historical_graph,
+            Err(err) => {
+                error!(
+                    error = err.to_string(),
+                    block_number, "[Replay] Error fetching pool states"
+                );
+                continue;
+            }
+        };
+
+        let gas_price = gas::fetch_historical_gas_price(config, block_number).await;
+        let cycles = cycler::find_cycles(&historical_graph, config, &gas_price);
+        let top_cycles = &cycles[..cycles.len().min(config.replay_top)];
+        let step = ReplayStep {
+            id: format!("{}-{}", replay_id, block_number),
+            replay_id: replay_id.clone(),
+            block_number: block_number as i64,
+            max_depth: config.depth as i32,
+            min_quote_amount: i64::from(config.min_quote_amount),
+            n_cycles: cycles.len() as i32,
+            used_ticks: config.replay_ticks,
+            top_quote_profits: top_cycles
+                .iter()
+                .map(|cycle| cycle.quote_profit.clone())
+                .collect(),
+            top_quote_net_profits: top_cycles
+                .iter()
+                .map(|cycle| cycle.quote_net_profit.clone())
+                .collect(),
+            top_symbol_paths: top_cycles
+                .iter()
+                .map(|cycle| cycle.symbol_path.clone())
+                .collect(),
+            created_at: Utc::now(),
+        };
+        step.save(&db_pool)
+            .await
+            .expect("Failed to save replay step");
+
+        info!(
+            block_number,
+            n_pools = historical_graph.pools.len(),
+            n_cycles = cycles.len(),
+            top_quote_net_profit = format!(
+                "{:.5}",
+                step.top_quote_net_profits
+                    .first()
+                    .cloned()
+                    .unwrap_or_default()
+            ),
+            "[Replay] Replayed block"
+        );
+    }
+}
+
+// Graph of the pools of `graph` that existed at `block_number`, in the state they were in then
+async fn load_historical_graph(
+    graph: &TokenGraph,
+    config: &Config,
+    block_number: u64,
+) -> Result<TokenGraph, SubgraphError> {
+    let pool_ids: Vec<String> = graph.pools.iter().map(|pool| pool.id.clone()).collect();
+    let mut states: HashMap<String, pool_states::poolStateFields> = HashMap::new();
+    for chunk in pool_ids.chunks(POOL_STATES_PAGE_SIZE) {
+        let query_vars = pool_states::Variables {
+            pool_ids: chunk.to_vec(),
+            n_pools: chunk.len() as i64,
+            block: block_number as i64,
+        };
+        let data =
+            explorer::query::<PoolStates>(&config.subgraph_url, "[PoolStateQuery]", "", query_vars)
+                .await?;
+        states.extend(
+            data.pools
+                .into_iter()
+                .map(|state| (state.id.clone(), state)),
+        );
+    }
+
+    let mut pools: Vec<Pool> = vec![];
+    let mut ticks_by_pool: HashMap<String, Vec<Tick>> = HashMap::new();
+    for pool in &graph.pools {
+        let Some(state) = states.get(&pool.id) else {
+            continue;
+        };
+        let pool = match historical_pool(pool, state, &graph.tokens, block_number) {
+            Ok(pool) => pool,
+            Err(err) => {
+                error!(
+                    pool_address = pool.id,
+                    error = err.to_string(),
+                    "[Replay] Invalid pool state"
+                );
+                continue;
+            }
+        };
+
+        if config.replay_ticks {
+            let ticks =
+                explorer::fetch_ticks_for_pool(&config.subgraph_url, &pool.id, block_number)
+                    .await?
+                    .iter()
+                    .map(|gql_tick| Tick::from_gql(&pool.id, gql_tick))
+                    .collect::<Result<Vec<Tick>, _>>();
+            match ticks {
+                Ok(ticks) => {
+                    ticks_by_pool.insert(pool.id.clone(), ticks);
+                }
+                Err(err) => {
+                    error!(
+                        pool_address = pool.id,
+                        error = err.to_string(),
+                        "[Replay] Invalid tick data"
+                    );
+                    continue;
+                }
+            }
+        }
+        pools.push(pool);
+    }
+
+    Ok(TokenGraph::new(graph.tokens.clone(), pools, ticks_by_pool))
+}
+
+// Stored pool with its price, liquidity and locked amounts as of `block_number`. The locked amounts
+// stand in for balances, which are not kept per block.
+fn historical_pool(
+    pool: &Pool,
+    state: &pool_states::poolStateFields,
+    tokens: &HashMap<String, GraphToken>,
+    block_number: u64,
+) -> Result<Pool, FieldError> {
+    let mut pool = pool.clone();
+    pool.token0_price = column::parse("token0_price", &state.token0_price)?;
+    pool.token1_price = column::parse("token1_price", &state.token1_price)?;
+    pool.total_value_locked_token0 = column::parse(
+        "total_value_locked_token0",
+        &state.total_value_locked_token0,
+    )?;
+    pool.total_value_locked_token1 = column::parse(
+        "total_value_locked_token1",
+        &state.total_value_locked_token1,
+    )?;
+    pool.liquidity = column::parse_u256("liquidity", &state.liquidity)?;
+    pool.sqrt_price = Some(column::parse_u256("sqrt_price", &state.sqrt_price)?);
+    pool.tick = state
+        .tick
+        .as_deref()
+        .map(|tick| column::parse("tick", tick))
+        .transpose()?;
+    pool.token0_balance = Some(raw_amount(
+        "token0_balance",
+        &pool.total_value_locked_token0,
+        tokens[&pool.token0_id].decimals,
+    )?);
+    pool.token1_balance = Some(raw_amount(
+        "token1_balance",
+        &pool.total_value_locked_token1,
+        tokens[&pool.token1_id].decimals,
+    )?);
+    pool.block_number = Some(block_number as i64);
+    Ok(pool)
+}
+
+// Raw token amount of a decimal amount, the subgraph's locked amounts can dip slightly below zero
+fn raw_amount(field: &'static str, amount: &BigDecimal, decimals: u32) -> Result<U256, FieldError> {
+    let raw_amount = (amount * BigDecimal::new(1.into(), -i64::from(decimals))).with_scale(0);
+    column::decimal_to_u256(field, &raw_amount.max(BigDecimal::from(0)))
+}
@@ -0,0 +1,105 @@
+use ethers_core::{
+    abi::{self, Token},
+    types::{Address, Bytes, U256},
+    utils,
+};
+
+const EXACT_INPUT_SIGNATURE: &str = "exactInput((bytes,address,uint256,uint256,uint256))";
+
+// Arguments of SwapRouter.exactInput
+pub struct ExactInputParams {
+    pub path: Bytes,
+    pub recipient: Address,
+    pub deadline: U256,
+    pub amount_in: U256,
+    pub amount_out_minimum: U256,
+}
+
+// Packs a swap path the way the router expects it: each token's 20 byte address followed by the
+// 3 byte fee tier of the pool leading to the next token
+pub fn encode_path(tokens: &[Address], fee_tiers: &[u32]) -> Bytes {
+    assert_eq!(
+        tokens.len(),
+        fee_tiers.len() + 1,
+        "path needs one more token than fees"
+    );
+    let mut path: Vec<u8> = Vec::with_capacity(tokens.len() * 20 + fee_tiers.len() * 3);
+
+    for (token, fee_tier) in tokens.iter().zip(fee_tiers) {
+        path.extend_from_slice(token.as_bytes());
+        path.extend_from_slice(&fee_tier.to_be_bytes()[1..]);
+    }
+    path.extend_from_slice(tokens[tokens.len() - 1].as_bytes());
+
+    path.into()
+}
+
+pub fn encode_exact_input(params: &ExactInputParams) -> Bytes {
+    let encoded_params = abi::encode(&[Token::Tuple(vec![
+        Token::Bytes(params.path.to_vec()),
+        Token::Address(params.recipient),
+        Token::Uint(params.deadline),
+        Token::Uint(params.amount_in),
+        Token::Uint(params.amount_out_minimum),
+    ])]);
+
+    let mut calldata = utils::id(EXACT_INPUT_SIGNATURE).to_vec();
+    calldata.extend(encoded_params);
+    calldata.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn path_packs_addresses_and_fee_tiers() {
+        let tokens: Vec<Address> = [
+            "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48",
+            "0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2",
+            "0xdac17f958d2ee523a2206206994597c13d831ec7",
+        ]
+        .iter()
+        .map(|token| token.parse().unwrap())
+        .collect();
+        let path = encode_path(&tokens, &[500, 3000]);
+
+        assert_eq!(path.len(), 20 + 3 + 20 + 3 + 20);
+        assert_eq!(&path[..20], tokens[0].as_bytes());
+        assert_eq!(&path[20..23], &[0x00, 0x01, 0xf4]);
+        assert_eq!(&path[23..43], tokens[1].as_bytes());
+        assert_eq!(&path[43..46], &[0x00, 0x0b, 0xb8]);
+        assert_eq!(&path[46..], tokens[2].as_bytes());
+    }
+
+    #[test]
+    fn exact_input_calldata_starts_with_selector() {
+        let path = encode_path(&[Address::repeat_byte(1), Address::repeat_byte(2)], &[3000]);
+        let calldata = encode_exact_input(&ExactInputParams {
+            path: path.clone(),
+            recipient: Address::repeat_byte(3),
+            deadline: U256::from(1000),
+            amount_in: U256::from(10),
+            amount_out_minimum: U256::from(10),
+        });
+
+        assert_eq!(&calldata[..4], &[0xc0, 0x4b, 0x8d, 0x59]);
+        let decoded = abi::decode(
+            &[abi::ParamType::Tuple(vec![
+                abi::ParamType::Bytes,
+                abi::ParamType::Address,
+                abi::ParamType::Uint(256),
+                abi::ParamType::Uint(256),
+                abi::ParamType::Uint(256),
+            ])],
+            &calldata[4..],
+        )
+        .unwrap();
+        let Token::Tuple(params) = &decoded[0] else {
+            panic!("exactInput takes a single tuple");
+        };
+        assert_eq!(params[0], Token::Bytes(path.to_vec()));
+        assert_eq!(params[1], Token::Address(Address::repeat_byte(3)));
+        assert_eq!(params[3], Token::Uint(U256::from(10)));
+    }
+}
@@ -0,0 +1,307 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use ethers_core::types::{Address, Bytes, H256, U256};
+use reqwest::StatusCode;
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{json, Value};
+use tokio::{sync::Mutex, time::Instant};
+use tracing::{info, warn};
+
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+
+// Error object returned by the node in place of a result
+#[derive(Clone, Debug, Deserialize)]
+pub struct ErrorObject {
+    pub code: i64,
+    pub message: String,
+    pub data: Option<Value>,
+}
+
+#[derive(Debug)]
+pub enum RPCError {
+    Transport(reqwest::Error),
+    Status(StatusCode, String),
+    Node(ErrorObject),
+    Decode(serde_json::Error),
+    MissingResponse,
+}
+
+impl fmt::Display for RPCError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RPCError::Transport(err) => write!(f, "transport error: {}", err),
+            RPCError::Status(status, body) => write!(f, "bad status {}: {}", status, body),
+            RPCError::Node(err) => match &err.data {
+                Some(data) => write!(f, "node error {}: {} ({})", err.code, err.message, data),
+                None => write!(f, "node error {}: {}", err.code, err.message),
+            },
+            RPCError::Decode(err) => write!(f, "invalid response: {}", err),
+            RPCError::MissingResponse => write!(f, "no response for request"),
+        }
+    }
+}
+
+impl From<reqwest::Error> for RPCError {
+    fn from(err: reqwest::Error) -> Self {
+        RPCError::Transport(err)
+    }
+}
+
+pub struct Request {
+    pub method: &'static str,
+    pub params: Value,
+}
+
+impl Request {
+    pub fn block_number() -> Self {
+        Self {
+            method: "eth_blockNumber",
+            params: json!([]),
+        }
+    }
+
+    // Header of `block_number`, or of the latest block when unset
+    pub fn block_by_number(block_number: Option<u64>) -> Self {
+        let block_tag = match block_number {
+            Some(block_number) => format!("{:#x}", block_number),
+            None => "latest".to_string(),
+        };
+        Self {
+            method: "eth_getBlockByNumber",
+            params: json!([block_tag, false]),
+        }
+    }
+
+    pub fn max_priority_fee_per_gas() -> Self {
+        Self {
+            method: "eth_maxPriorityFeePerGas",
+            params: json!([]),
+        }
+    }
+
+    // Call pinned to `block_number`, so that every read of a refresh sees the same state
+    pub fn eth_call(from: Option<Address>, to: Address, data: Bytes, block_number: u64) -> Self {
+        let mut call = json!({ "to": to, "data": data });
+        if let Some(from) = from {
+            call["from"] = json!(from);
+        }
+        Self {
+            method: "eth_call",
+            params: json!([call, format!("{:#x}", block_number)]),
+        }
+    }
+
+    // Call pinned to `block_number` that runs `code` in place of the runtime code of `to`, through
+    // the state override set of Geth and Anvil
+    pub fn eth_call_with_code(to: Address, data: Bytes, code: Bytes, block_number: u64) -> Self {
+        let mut overrides = serde_json::Map::new();
+        overrides.insert(format!("{:?}", to), json!({ "code": code }));
+        Self {
+            method: "eth_call",
+            params: json!([
+                { "to": to, "data": data },
+                format!("{:#x}", block_number),
+                overrides,
+            ]),
+        }
+    }
+
+    // Logs of `address` matching `topic0` in the inclusive block range
+    pub fn get_logs(address: Address, topic0: H256, from_block: u64, to_block: u64) -> Self {
+        Self {
+            method: "eth_getLogs",
+            params: json!([{
+                "address": address,
+                "topics": [topic0],
+                "fromBlock": format!("{:#x}", from_block),
+                "toBlock": format!("{:#x}", to_block),
+            }]),
+        }
+    }
+}
+
+// The part of a block header we read, blocks before London have no base fee
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BlockHeader {
+    base_fee_per_gas: Option<U256>,
+}
+
+#[derive(Deserialize)]
+struct Response {
+    id: u64,
+    result: Option<Value>,
+    error: Option<ErrorObject>,
+}
+
+// Some nodes answer a whole batch with a single error object, e.g. when it is too large
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum BatchResponse {
+    Batch(Vec<Response>),
+    Error { error: ErrorObject },
+}
+
+pub struct RPCClient {
+    url: String,
+    http: reqwest::Client,
+    batch_size: usize,
+    max_retries: u32,
+    // Minimum time between two HTTP requests to the node
+    min_interval: Duration,
+    next_request_at: Mutex<Instant>,
+    next_id: AtomicU64,
+}
+
+impl RPCClient {
+    pub fn new(url: &str, batch_size: usize, max_retries: u32, min_interval: Duration) -> Self {
+        Self {
+            url: url.to_string(),
+            http: reqwest::Client::new(),
+            batch_size: batch_size.max(1),
+            max_retries,
+            min_interval,
+            next_request_at: Mutex::new(Instant::now()),
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    pub async fn request<T: DeserializeOwned>(&self, request: Request) -> Result<T, RPCError> {
+        self.batch(vec![request])
+            .await?
+            .pop()
+            .unwrap_or(Err(RPCError::MissingResponse))
+    }
+
+    // Sends the requests in batches of `batch_size`, returning one result per request in the same
+    // order. Fails as a whole only when a batch cannot be delivered after every retry.
+    pub async fn batch<T: DeserializeOwned>(
+        &self,
+        requests: Vec<Request>,
+    ) -> Result<Vec<Result<T, RPCError>>, RPCError> {
+        let n_batches = requests.len().div_ceil(self.batch_size);
+        let mut results: Vec<Result<T, RPCError>> = Vec::with_capacity(requests.len());
+
+        for (i, chunk) in requests.chunks(self.batch_size).enumerate() {
+            let mut index_by_id: HashMap<u64, usize> = HashMap::new();
+            let body: Vec<Value> = chunk
+                .iter()
+                .enumerate()
+                .map(|(idx, request)| {
+                    let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+                    index_by_id.insert(id, idx);
+                    json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "method": request.method,
+                        "params": request.params,
+                    })
+                })
+                .collect();
+
+            let mut chunk_results: Vec<Result<T, RPCError>> = chunk
+                .iter()
+                .map(|_| Err(RPCError::MissingResponse))
+                .collect();
+            let responses = match self.send(&body).await? {
+                BatchResponse::Batch(responses) => responses,
+                BatchResponse::Error { error } => return Err(RPCError::Node(error)),
+            };
+            for response in responses {
+                let Some(&idx) = index_by_id.get(&response.id) else {
+                    warn!(id = response.id, "[RPC] Response with unknown id");
+                    continue;
+                };
+                chunk_results[idx] = match (response.result, response.error) {
+                    (_, Some(err)) => Err(RPCError::Node(err)),
+                    (Some(result), None) => {
+                        serde_json::from_value(result).map_err(RPCError::Decode)
+                    }
+                    (None, None) => Err(RPCError::MissingResponse),
+                };
+            }
+            results.append(&mut chunk_results);
+
+            if n_batches > 1 {
+                info!("[RPC] Finished batch={}/{}", i + 1, n_batches);
+            }
+        }
+
+        Ok(results)
+    }
+
+    pub async fn block_number(&self) -> Result<u64, RPCError> {
+        let block_number: U256 = self.request(Request::block_number()).await?;
+        Ok(block_number.as_u64())
+    }
+
+    pub async fn base_fee(&self, block_number: Option<u64>) -> Result<Option<U256>, RPCError> {
+        let header: BlockHeader = self.request(Request::block_by_number(block_number)).await?;
+        Ok(header.base_fee_per_gas)
+    }
+
+    pub async fn max_priority_fee(&self) -> Result<U256, RPCError> {
+        self.request(Request::max_priority_fee_per_gas()).await
+    }
+
+    pub async fn eth_call(
+        &self,
+        from: Option<Address>,
+        to: Address,
+        data: Bytes,
+        block_number: u64,
+    ) -> Result<Bytes, RPCError> {
+        self.request(Request::eth_call(from, to, data, block_number))
+            .await
+    }
+
+    // Posts a batch body, retrying transport errors, rate limits and server errors with exponential
+    // backoff
+    async fn send(&self, body: &[Value]) -> Result<BatchResponse, RPCError> {
+        let mut backoff = INITIAL_BACKOFF;
+        let mut attempt = 0;
+
+        loop {
+            self.wait_for_rate_limit().await;
+            let err = match self.http.post(&self.url).json(body).send().await {
+                Ok(response) if response.status().is_success() => {
+                    let text = response.text().await?;
+                    return serde_json::from_str(&text).map_err(RPCError::Decode);
+                }
+                Ok(response) => {
+                    let status = response.status();
+                    let text = response.text().await.unwrap_or_default();
+                    if status != StatusCode::TOO_MANY_REQUESTS && !status.is_server_error() {
+                        return Err(RPCError::Status(status, text));
+                    }
+                    RPCError::Status(status, text)
+                }
+                Err(err) => RPCError::Transport(err),
+            };
+
+            if attempt >= self.max_retries {
+                return Err(err);
+            }
+            attempt += 1;
+            warn!(
+                error = err.to_string(),
+                attempt,
+                backoff_ms = backoff.as_millis() as u64,
+                "[RPC] Request failed, retrying"
+            );
+            tokio::time::sleep(backoff).await;
+            backoff *= 2;
+        }
+    }
+
+    async fn wait_for_rate_limit(&self) {
+        let mut next_request_at = self.next_request_at.lock().await;
+        tokio::time::sleep_until(*next_request_at).await;
+        *next_request_at = Instant::now() + self.min_interval;
+    }
+}
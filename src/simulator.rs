@@ -0,0 +1,45 @@
+use std::fmt;
+
+use crate::rpc::{RPCClient, RPCError};
+use ethers_core::{
+    abi::{self, ParamType},
+    types::{Address, Bytes, U256},
+};
+
+#[derive(Debug)]
+pub enum SimulationError {
+    Rpc(RPCError),
+    Decode(abi::Error),
+}
+
+impl fmt::Display for SimulationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SimulationError::Rpc(err) => write!(f, "{}", err),
+            SimulationError::Decode(err) => write!(f, "failed to decode swap output: {}", err),
+        }
+    }
+}
+
+// Runs SwapRouter calldata with `eth_call` at `block_number` and returns the amount the router
+// reports as swapped out. `from` has to hold and have approved the input amount, which on a local
+// fork can be any impersonated or funded account.
+pub async fn simulate_swap(
+    client: &RPCClient,
+    router: Address,
+    from: Address,
+    calldata: Bytes,
+    block_number: u64,
+) -> Result<U256, SimulationError> {
+    let output = client
+        .eth_call(Some(from), router, calldata, block_number)
+        .await
+        .map_err(SimulationError::Rpc)?;
+
+    let amount_out = abi::decode(&[ParamType::Uint(256)], &output)
+        .map_err(SimulationError::Decode)?
+        .remove(0)
+        .into_uint()
+        .unwrap();
+    Ok(amount_out)
+}
@@ -0,0 +1,486 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+
+use chrono::Utc;
+use ethers_core::{
+    abi::{self, Token},
+    types::{Address, Bytes, U256},
+};
+use tracing::{error, info, warn};
+
+use crate::{
+    balancer,
+    config::Config,
+    db::db_connection,
+    models::{Model, TokenFlag},
+    rpc::{RPCError, Request},
+};
+
+type DBPool = sqlx::Pool<sqlx::Postgres>;
+
+// Runtime code run at a holder's address through a state override, so that it can move the holder's
+// tokens. Called with `(token, recipient, amount)`, it transfers `amount` to `recipient` and returns
+// the balances of the recipient and the holder before the transfer, then after it, followed by the
+// word `transfer` returned, 1 when it returned nothing. Any failing call reverts with the token's
+// revert data.
+const TRANSFER_PROBE_CODE: &str = "0x6370a0823160e01b60005260203560045260206080602460006000355afa\
+    156100bf576370a0823160e01b60005230600452602060a0602460006000355afa156100bf5760016101005263a9059c\
+    bb60e01b60005260203560045260403560245260206101006044600060006000355af1156100bf576370a0823160e01b\
+    600052602035600452602060c0602460006000355afa156100bf576370a0823160e01b60005230600452602060e06024\
+    60006000355afa156100bf5760a06080f35b3d600060003e3d6000fd";
+const PROBE_RECIPIENT: &str = "0x000000000000000000000000000000000000bEEF";
+// Share of the holder's balance transferred by the probe
+const PROBE_AMOUNT_DIVISOR: u64 = 1000;
+// Words returned by the probe
+const PROBE_OUTPUT_WORDS: usize = 5;
+
+// Flags the tokens listed in the blacklist file, then the tokens failing the transfer check on the
+// fork, for the explorer and the cycler to skip
+pub async fn update_token_flags(config: &Config) {
+    let db_pool = db_connection().await;
+    if let Some(blacklist_file) = &config.blacklist_file {
+        load_blacklist_file(&db_pool, blacklist_file).await;
+    }
+    match &config.fork_url {
+        Some(fork_url) => check_transfers(&db_pool, fork_url, config).await,
+        None => warn!("[TokenFlags] No fork node set, skipping the transfer check"),
+    }
+}
+
+// Reads one token address per line, optionally followed by a comma and a reason. Blank lines and
+// anything after a `#` are ignored.
+async fn load_blacklist_file(db_pool: &DBPool, path: &str) {
+    let contents = fs::read_to_string(path).expect("Failed to read blacklist file");
+
+    let mut n_flagged = 0;
+    for line in contents.lines() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let (token, reason) = match line.split_once(',') {
+            Some((token, reason)) => (token.trim(), reason.trim()),
+            None => (line, "blacklisted"),
+        };
+        let Ok(token) = token.parse::<Address>() else {
+            error!(line, "[TokenFlags] Invalid token address in blacklist file");
+            continue;
+        };
+
+        TokenFlag {
+            id: format!("{:?}", token),
+            reason: reason.to_string(),
+            source: "file".to_string(),
+            flagged_at: Utc::now(),
+        }
+        .save(db_pool)
+        .await
+        .expect("Failed to save token flag");
+        n_flagged += 1;
+    }
+
+    info!(path, n_flagged, "[TokenFlags] Loaded blacklist file");
+}
+
+// Transfers a small amount of every unflagged token out of the pool holding the most of it, with
+// `eth_call` on the fork, and flags the tokens whose balances do not move by exactly that amount or
+// whose transfer reverts or returns false
+async fn check_transfers(db_pool: &DBPool, fork_url: &str, config: &Config) {
+    let client = config.rpc_client(fork_url);
+    let block_number = match config.simulation_block {
+        Some(block_number) => block_number,
+        None => match client.block_number().await {
+            Ok(block_number) => block_number,
+            Err(err) => {
+                error!(
+                    error = err.to_string(),
+                    "[TokenFlags] Error fetching fork block number"
+                );
+                return;
+            }
+        },
+    };
+
+    let flagged: HashSet<String> = TokenFlag::flagged_ids(db_pool)
+        .await
+        .expect("Failed to fetch token flags")
+        .into_iter()
+        .collect();
+    let mut holders: HashMap<String, (String, U256)> = HashMap::new();
+    for pool in balancer::fetch_all_pools(db_pool).await {
+        for (token_id, balance) in [
+            (&pool.token0_id, pool.token0_balance),
+            (&pool.token1_id, pool.token1_balance),
+        ] {
+            let Some(balance) = balance else {
+                continue;
+            };
+            if flagged.contains(token_id) || balance < U256::from(PROBE_AMOUNT_DIVISOR) {
+                continue;
+            }
+            match holders.get(token_id) {
+                Some((_, held)) if *held >= balance => (),
+                _ => {
+                    holders.insert(token_id.clone(), (pool.id.clone(), balance));
+                }
+            }
+        }
+    }
+    info!(
+        block_number,
+        n_tokens = holders.len(),
+        "[TokenFlags] Checking transfers"
+    );
+
+    let recipient: Address = PROBE_RECIPIENT.parse().unwrap();
+    let code: Bytes = TRANSFER_PROBE_CODE.parse().unwrap();
+    let checks: Vec<(String, U256)> = holders
+        .iter()
+        .map(|(token_id, (_, balance))| (token_id.clone(), balance / PROBE_AMOUNT_DIVISOR))
+        .collect();
+    let requests: Vec<Request> = checks
+        .iter()
+        .map(|(token_id, amount)| {
+            let token: Address = token_id.parse().expect("Invalid token address");
+            let holder: Address = holders[token_id].0.parse().expect("Invalid pool address");
+            let calldata = abi::encode(&[
+                Token::Address(token),
+                Token::Address(recipient),
+                Token::Uint(*amount),
+            ]);
+            Request::eth_call_with_code(holder, calldata.into(), code.clone(), block_number)
+        })
+        .collect();
+
+    let results = match client.batch::<Bytes>(requests).await {
+        Ok(results) => results,
+        Err(err) => {
+            error!(
+                error = err.to_string(),
+                "[TokenFlags] Error checking transfers"
+            );
+            return;
+        }
+    };
+
+    let mut n_flagged = 0;
+    for ((token_id, amount), result) in checks.into_iter().zip(results) {
+        let reason = match transfer_check_reason(&result, amount) {
+            Ok(Some(reason)) => reason,
+            Ok(None) => continue,
+            Err(err) => {
+                error!(
+                    error = err,
+                    token_id, "[TokenFlags] Error checking transfer"
+                );
+                continue;
+            }
+        };
+        if let Err(RPCError::Node(err)) = &result {
+            warn!(
+                token_id,
+                error = err.message,
+                "[TokenFlags] Transfer reverted"
+            );
+        }
+
+        TokenFlag {
+            id: token_id,
+            reason: reason.to_string(),
+            source: "transfer_check".to_string(),
+            flagged_at: Utc::now(),
+        }
+        .save(db_pool)
+        .await
+        .expect("Failed to save token flag");
+        n_flagged += 1;
+    }
+
+    info!(n_flagged, "[TokenFlags] Checked transfers");
+}
+
+// Why a token fails the transfer check, given the probe's result, or `None` when `transfer`
+// succeeded and the recipient received and the holder sent exactly `amount`. Errors when the probe
+// could not run.
+fn transfer_check_reason(
+    result: &Result<Bytes, RPCError>,
+    amount: U256,
+) -> Result<Option<&'static str>, String> {
+    let output = match result {
+        Ok(output) => output,
+        Err(RPCError::Node(_)) => return Ok(Some("transfer_reverted")),
+        Err(err) => return Err(err.to_string()),
+    };
+    // A node ignoring the state override runs the pool's own code instead of the probe
+    if output.len() != PROBE_OUTPUT_WORDS * 32 {
+        return Err(format!(
+            "unexpected probe output of {} bytes, does the fork support state overrides?",
+            output.len()
+        ));
+    }
+
+    let word = |idx: usize| U256::from_big_endian(&output[idx * 32..(idx + 1) * 32]);
+    let (recipient_before, holder_before) = (word(0), word(1));
+    let (recipient_after, holder_after) = (word(2), word(3));
+    let transfer_returned = word(4);
+
+    let received = recipient_after.saturating_sub(recipient_before);
+    let sent = holder_before.saturating_sub(holder_after);
+    let reason = if transfer_returned.is_zero() {
+        Some("transfer_returned_false")
+    } else if received < amount {
+        Some("fee_on_transfer")
+    } else if received != amount || sent != amount {
+        Some("transfer_mismatch")
+    } else {
+        None
+    };
+    Ok(reason)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc::ErrorObject;
+
+    const BALANCE_OF: [u8; 4] = [0x70, 0xa0, 0x82, 0x31];
+    const TRANSFER: [u8; 4] = [0xa9, 0x05, 0x9c, 0xbb];
+
+    #[derive(Clone, Copy)]
+    enum Behavior {
+        Standard,
+        ReturnsNothing,
+        ReturnsFalse,
+        Reverts,
+        // Burns the given percentage of every transfer
+        Fee(u64),
+    }
+
+    // ERC-20 the probe calls into
+    struct MockToken {
+        address: Address,
+        balances: HashMap<Address, U256>,
+        behavior: Behavior,
+    }
+
+    impl MockToken {
+        // Handles a call from `caller`, returning whether it succeeded and its return data
+        fn call(&mut self, caller: Address, input: &[u8]) -> (bool, Vec<u8>) {
+            let arg = |idx: usize| &input[4 + idx * 32..4 + (idx + 1) * 32];
+            let address = |idx: usize| Address::from_slice(&arg(idx)[12..]);
+            let selector: [u8; 4] = input[..4].try_into().unwrap();
+            match selector {
+                BALANCE_OF => {
+                    let balance = self.balances.get(&address(0)).copied().unwrap_or_default();
+                    (true, abi::encode(&[Token::Uint(balance)]))
+                }
+                TRANSFER => {
+                    let (to, amount) = (address(0), U256::from_big_endian(arg(1)));
+                    let received = match self.behavior {
+                        Behavior::Reverts => return (false, b"paused".to_vec()),
+                        Behavior::ReturnsFalse => {
+                            return (true, abi::encode(&[Token::Bool(false)]))
+                        }
+                        Behavior::Fee(percent) => amount - amount * percent / 100,
+                        Behavior::Standard | Behavior::ReturnsNothing => amount,
+                    };
+                    *self.balances.get_mut(&caller).unwrap() -= amount;
+                    *self.balances.entry(to).or_default() += received;
+                    match self.behavior {
+                        Behavior::ReturnsNothing => (true, vec![]),
+                        _ => (true, abi::encode(&[Token::Bool(true)])),
+                    }
+                }
+                _ => panic!("unexpected selector {:?}", selector),
+            }
+        }
+    }
+
+    fn pop(stack: &mut Vec<U256>) -> usize {
+        stack.pop().expect("stack underflow").as_usize()
+    }
+
+    // Runs the transfer probe at `holder`'s address, interpreting the opcodes it uses, and returns
+    // its output or its revert data
+    fn run_probe(token: &mut MockToken, holder: Address, amount: U256) -> Result<Bytes, Vec<u8>> {
+        let code: Bytes = TRANSFER_PROBE_CODE.parse().unwrap();
+        let recipient: Address = PROBE_RECIPIENT.parse().unwrap();
+        let calldata = abi::encode(&[
+            Token::Address(token.address),
+            Token::Address(recipient),
+            Token::Uint(amount),
+        ]);
+        let to_word = |address: Address| U256::from_big_endian(address.as_bytes());
+
+        let mut stack: Vec<U256> = vec![];
+        let mut memory = vec![0u8; 0x200];
+        let mut return_data: Vec<u8> = vec![];
+        let mut pc = 0;
+        loop {
+            let op = code[pc];
+            pc += 1;
+            match op {
+                // PUSH1 to PUSH32
+                0x60..=0x7f => {
+                    let size = (op - 0x5f) as usize;
+                    stack.push(U256::from_big_endian(&code[pc..pc + size]));
+                    pc += size;
+                }
+                // SHL
+                0x1b => {
+                    let shift = pop(&mut stack);
+                    let value = stack.pop().unwrap();
+                    stack.push(value << shift);
+                }
+                // ISZERO
+                0x15 => {
+                    let value = stack.pop().unwrap();
+                    stack.push(U256::from(value.is_zero() as u8));
+                }
+                // ADDRESS
+                0x30 => stack.push(to_word(holder)),
+                // CALLDATALOAD
+                0x35 => {
+                    let offset = pop(&mut stack);
+                    stack.push(U256::from_big_endian(&calldata[offset..offset + 32]));
+                }
+                // RETURNDATASIZE
+                0x3d => stack.push(U256::from(return_data.len())),
+                // RETURNDATACOPY
+                0x3e => {
+                    let (dest, offset, size) = (pop(&mut stack), pop(&mut stack), pop(&mut stack));
+                    memory[dest..dest + size].copy_from_slice(&return_data[offset..offset + size]);
+                }
+                // MSTORE
+                0x52 => {
+                    let offset = pop(&mut stack);
+                    stack
+                        .pop()
+                        .unwrap()
+                        .to_big_endian(&mut memory[offset..offset + 32]);
+                }
+                // JUMPI
+                0x57 => {
+                    let (dest, condition) = (pop(&mut stack), pop(&mut stack));
+                    if condition != 0 {
+                        assert_eq!(code[dest], 0x5b, "jump to a non-JUMPDEST");
+                        pc = dest;
+                    }
+                }
+                // GAS
+                0x5a => stack.push(U256::from(u64::MAX)),
+                // JUMPDEST
+                0x5b => (),
+                // CALL and STATICCALL
+                0xf1 | 0xfa => {
+                    let (_gas, address) = (pop(&mut stack), stack.pop().unwrap());
+                    if op == 0xf1 {
+                        assert!(pop(&mut stack) == 0, "probe sends ether");
+                    }
+                    let (args_offset, args_size, ret_offset, ret_size) = (
+                        pop(&mut stack),
+                        pop(&mut stack),
+                        pop(&mut stack),
+                        pop(&mut stack),
+                    );
+                    assert_eq!(address, to_word(token.address));
+                    let (success, output) =
+                        token.call(holder, &memory[args_offset..args_offset + args_size]);
+                    let copied = ret_size.min(output.len());
+                    memory[ret_offset..ret_offset + copied].copy_from_slice(&output[..copied]);
+                    return_data = output;
+                    stack.push(U256::from(success as u8));
+                }
+                // RETURN
+                0xf3 => {
+                    let (offset, size) = (pop(&mut stack), pop(&mut stack));
+                    return Ok(memory[offset..offset + size].to_vec().into());
+                }
+                // REVERT
+                0xfd => {
+                    let (offset, size) = (pop(&mut stack), pop(&mut stack));
+                    return Err(memory[offset..offset + size].to_vec());
+                }
+                _ => panic!("unexpected opcode {:#04x} at {}", op, pc - 1),
+            }
+        }
+    }
+
+    // Runs the probe against a token with `behavior` and checks its result
+    fn check(behavior: Behavior) -> Result<Option<&'static str>, String> {
+        let holder = Address::from_low_u64_be(0x1234);
+        let mut token = MockToken {
+            address: Address::from_low_u64_be(0x7070),
+            balances: HashMap::from([(holder, U256::from(1_000_000))]),
+            behavior,
+        };
+        let amount = U256::from(1_000);
+        let result = run_probe(&mut token, holder, amount).map_err(|revert_data| {
+            RPCError::Node(ErrorObject {
+                code: 3,
+                message: "execution reverted".to_string(),
+                data: Some(Bytes::from(revert_data).to_string().into()),
+            })
+        });
+        transfer_check_reason(&result, amount)
+    }
+
+    #[test]
+    fn probe_passes_standard_tokens() {
+        assert_eq!(check(Behavior::Standard), Ok(None));
+        assert_eq!(check(Behavior::ReturnsNothing), Ok(None));
+    }
+
+    #[test]
+    fn probe_flags_failing_transfers() {
+        assert_eq!(check(Behavior::Reverts), Ok(Some("transfer_reverted")));
+        assert_eq!(
+            check(Behavior::ReturnsFalse),
+            Ok(Some("transfer_returned_false"))
+        );
+        assert_eq!(check(Behavior::Fee(1)), Ok(Some("fee_on_transfer")));
+    }
+
+    // Probe output with the balances before and after the transfer and `transfer`'s return word
+    fn output(balances: [u64; 4], transfer_returned: u64) -> Result<Bytes, RPCError> {
+        let words = balances.into_iter().chain([transfer_returned]);
+        Ok(abi::encode(
+            &words
+                .map(|word| Token::Uint(word.into()))
+                .collect::<Vec<_>>(),
+        )
+        .into())
+    }
+
+    #[test]
+    fn transfer_check_reasons() {
+        let amount = U256::from(100);
+        let reverted = Err(RPCError::Node(ErrorObject {
+            code: 3,
+            message: "execution reverted".to_string(),
+            data: None,
+        }));
+        assert_eq!(
+            transfer_check_reason(&reverted, amount),
+            Ok(Some("transfer_reverted"))
+        );
+        assert_eq!(
+            transfer_check_reason(&output([0, 1000, 0, 1000], 0), amount),
+            Ok(Some("transfer_returned_false"))
+        );
+        assert_eq!(
+            transfer_check_reason(&output([0, 1000, 98, 900], 1), amount),
+            Ok(Some("fee_on_transfer"))
+        );
+        assert_eq!(
+            transfer_check_reason(&output([0, 1000, 100, 890], 1), amount),
+            Ok(Some("transfer_mismatch"))
+        );
+        assert_eq!(
+            transfer_check_reason(&output([0, 1000, 100, 900], 1), amount),
+            Ok(None)
+        );
+        assert!(transfer_check_reason(&Ok(Bytes::from(vec![0; 128])), amount).is_err());
+        assert!(transfer_check_reason(&Err(RPCError::MissingResponse), amount).is_err());
+    }
+}
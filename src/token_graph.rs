@@ -0,0 +1,212 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use bigdecimal::ToPrimitive;
+use ethers_core::types::U256;
+use sqlx::query_as;
+
+use crate::{
+    models::{Pool, Tick, Token},
+    v3_math::TickLiquidity,
+};
+
+// Pools the graph is built from: active, within `$1` hops when set, and not touching a flagged token
+const LOADED_POOLS_CONDITION: &str = "pools.active
+    AND ($1::integer IS NULL OR pools.hops <= $1)
+    AND pools.token0_id NOT IN (SELECT id FROM token_flags)
+    AND pools.token1_id NOT IN (SELECT id FROM token_flags)";
+
+#[derive(Clone)]
+pub struct GraphToken {
+    pub id: String,
+    pub symbol: String,
+    pub decimals: u32,
+}
+
+// Directed swap from `from_token` to `to_token` through a pool, weighted by `-ln(fee_price)` so that
+// a profitable cycle has a negative total weight
+pub struct Edge {
+    pub pool: usize,
+    pub from_token: String,
+    pub to_token: String,
+    // Spot price of `from_token` in units of `to_token`, before fees
+    pub price: f64,
+    pub weight: f64,
+    // Pool balance of `to_token`, in token units
+    pub out_balance: f64,
+}
+
+// Snapshot of every pool, token and initialized tick, loaded once so that cycle finding and swap
+// simulation never go back to the database
+pub struct TokenGraph {
+    pub tokens: HashMap<String, GraphToken>,
+    pub pools: Vec<Pool>,
+    pub pool_ticks: HashMap<String, Vec<TickLiquidity>>,
+    pub edges: Vec<Edge>,
+    // Latest block the loaded pools were snapshotted at
+    pub block_number: Option<u64>,
+    adjacency: BTreeMap<String, Vec<usize>>,
+}
+
+impl TokenGraph {
+    // Loads the active pools between unflagged tokens, only those at most `max_hops` swaps from a
+    // root token when set
+    pub async fn load(
+        db_pool: &sqlx::Pool<sqlx::Postgres>,
+        max_hops: Option<u32>,
+    ) -> Result<Self, sqlx::Error> {
+        let max_hops = max_hops.map(|max_hops| max_hops as i32);
+        let tokens: HashMap<String, GraphToken> = query_as::<_, Token>("SELECT * FROM tokens")
+            .fetch_all(db_pool)
+            .await?
+            .into_iter()
+            .map(|token| {
+                let graph_token = GraphToken {
+                    decimals: token.decimals.into(),
+                    symbol: token.symbol,
+                    id: token.id,
+                };
+                (graph_token.id.clone(), graph_token)
+            })
+            .collect();
+        let pools = query_as::<_, Pool>(&format!(
+            "SELECT * FROM pools WHERE {} ORDER BY id",
+            LOADED_POOLS_CONDITION
+        ))
+        .bind(max_hops)
+        .fetch_all(db_pool)
+        .await?;
+
+        let mut ticks_by_pool: HashMap<String, Vec<Tick>> = HashMap::new();
+        for tick in query_as::<_, Tick>(&format!(
+            "SELECT ticks.* FROM ticks JOIN pools ON pools.id = ticks.pool_id WHERE {}",
+            LOADED_POOLS_CONDITION
+        ))
+        .bind(max_hops)
+        .fetch_all(db_pool)
+        .await?
+        {
+            ticks_by_pool
+                .entry(tick.pool_id.clone())
+                .or_default()
+                .push(tick);
+        }
+
+        Ok(Self::new(tokens, pools, ticks_by_pool))
+    }
+
+    // Builds the graph from pools and ticks already in memory, such as historical pool states
+    pub fn new(
+        tokens: HashMap<String, GraphToken>,
+        pools: Vec<Pool>,
+        ticks_by_pool: HashMap<String, Vec<Tick>>,
+    ) -> Self {
+        let pool_ticks = ticks_by_pool
+            .into_iter()
+            .map(|(pool_id, ticks)| (pool_id, Tick::liquidity_ticks(&ticks)))
+            .collect();
+
+        let block_number = pools
+            .iter()
+            .filter_map(|pool| pool.block_number)
+            .max()
+            .map(|block_number| block_number as u64);
+
+        let mut edges: Vec<Edge> = vec![];
+        let mut adjacency: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+        for (pool_idx, pool) in pools.iter().enumerate() {
+            // Stored pools always have their token rows, but the pools and tokens handed to `new`
+            // need not match, and a pool whose token is missing has no decimals to size balances
+            let (Some(token0), Some(token1)) =
+                (tokens.get(&pool.token0_id), tokens.get(&pool.token1_id))
+            else {
+                continue;
+            };
+            for (from_token, to_token, out_balance) in [
+                (&pool.token0_id, token1, &pool.token1_balance),
+                (&pool.token1_id, token0, &pool.token0_balance),
+            ] {
+                let price = pool.price_for(from_token).to_f64().unwrap_or_default();
+                let fee_price = pool.fee_price_for(from_token).to_f64().unwrap_or_default();
+                if price <= 0.0 || fee_price <= 0.0 {
+                    continue;
+                }
+
+                adjacency
+                    .entry(from_token.clone())
+                    .or_default()
+                    .push(edges.len());
+                edges.push(Edge {
+                    pool: pool_idx,
+                    from_token: from_token.clone(),
+                    to_token: to_token.id.clone(),
+                    price,
+                    weight: -fee_price.ln(),
+                    out_balance: token_amount(*out_balance, to_token.decimals),
+                });
+            }
+        }
+
+        Self {
+            tokens,
+            pools,
+            pool_ticks,
+            edges,
+            block_number,
+            adjacency,
+        }
+    }
+
+    // Indices into `edges` of every swap selling `token_id`
+    pub fn edges_from(&self, token_id: &str) -> &[usize] {
+        self.adjacency
+            .get(token_id)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    // Price of every token reachable from `quote_token_id`, in units of the quote token. Prices are
+    // chained along the fewest possible hops, preferring the pool holding the most of the newly
+    // priced token when several pools reach it at the same distance.
+    pub fn quote_prices(&self, quote_token_id: &str) -> HashMap<String, f64> {
+        let mut prices: HashMap<String, f64> = HashMap::from([(quote_token_id.to_string(), 1.0)]);
+        let mut frontier: VecDeque<String> = VecDeque::from([quote_token_id.to_string()]);
+
+        while !frontier.is_empty() {
+            let mut next_layer: BTreeMap<&str, (f64, f64)> = BTreeMap::new();
+            for token_id in frontier.drain(..) {
+                let token_price = prices[&token_id];
+                for &edge_idx in self.edges_from(&token_id) {
+                    let edge = &self.edges[edge_idx];
+                    if prices.contains_key(&edge.to_token) {
+                        continue;
+                    }
+
+                    let candidate = (token_price / edge.price, edge.out_balance);
+                    let is_deeper = match next_layer.get(edge.to_token.as_str()) {
+                        Some(&(_, out_balance)) => edge.out_balance > out_balance,
+                        None => true,
+                    };
+                    if is_deeper {
+                        next_layer.insert(&edge.to_token, candidate);
+                    }
+                }
+            }
+
+            for (token_id, (price, _)) in next_layer {
+                prices.insert(token_id.to_string(), price);
+                frontier.push_back(token_id.to_string());
+            }
+        }
+
+        prices
+    }
+}
+
+fn token_amount(raw_amount: Option<U256>, decimals: u32) -> f64 {
+    match raw_amount {
+        Some(raw_amount) => {
+            raw_amount.to_string().parse::<f64>().unwrap() / 10_f64.powi(decimals as i32)
+        }
+        None => 0.0,
+    }
+}
@@ -0,0 +1,189 @@
+use ethers_core::{
+    abi::{self, ParamType},
+    types::{Address, Bytes, U256},
+    utils::id,
+};
+use tracing::{error, info};
+
+use crate::{
+    config::Config,
+    db::db_connection,
+    models::{Model, Token},
+    rpc::{RPCClient, Request},
+};
+
+// Token getters read for every token, in the order their results are decoded
+const TOKEN_GETTERS: [&str; 3] = ["symbol()", "name()", "decimals()"];
+
+pub struct TokenMetadata {
+    pub symbol: Option<String>,
+    pub name: Option<String>,
+    pub decimals: u8,
+}
+
+impl TokenMetadata {
+    // Token row for `token`, using the address as symbol when the token has none
+    pub fn into_token(self, token: Address) -> Token {
+        let id = format!("{:?}", token);
+        Token {
+            symbol: self.symbol.unwrap_or_else(|| id.clone()),
+            name: self.name,
+            decimals: self.decimals,
+            id,
+        }
+    }
+}
+
+// Resolves symbol, name and decimals on-chain for the stored tokens that are still missing them,
+// such as tokens discovered before names were tracked
+pub async fn update_missing_token_metadata(config: &Config) {
+    let db_pool = db_connection().await;
+    let client = config.rpc_client(config.node_url());
+    let tokens =
+        sqlx::query_as::<_, Token>("SELECT * FROM tokens WHERE name IS NULL OR symbol = id")
+            .fetch_all(&db_pool)
+            .await
+            .expect("Failed to fetch tokens");
+    let block_number = match client.block_number().await {
+        Ok(block_number) => block_number,
+        Err(err) => {
+            error!(
+                error = err.to_string(),
+                "[TokenMetadata] Error fetching block number"
+            );
+            return;
+        }
+    };
+
+    let addresses: Vec<Address> = tokens
+        .iter()
+        .map(|token| token.id.parse().expect("Invalid token address"))
+        .collect();
+    let metadata = match resolve_token_metadata(&client, &addresses, block_number).await {
+        Ok(metadata) => metadata,
+        Err(err) => {
+            error!(error = err, "[TokenMetadata] Error resolving tokens");
+            return;
+        }
+    };
+
+    let mut n_resolved = 0;
+    for (address, metadata) in addresses.into_iter().zip(metadata) {
+        match metadata {
+            Ok(metadata) => {
+                let token = metadata.into_token(address);
+                if let Err(err) = token.save(&db_pool).await {
+                    error!(
+                        error = err.to_string(),
+                        "[TokenMetadata] Error saving token"
+                    );
+                    continue;
+                }
+                n_resolved += 1;
+            }
+            Err(err) => error!(
+                error = err,
+                token_id = format!("{:?}", address),
+                "[TokenMetadata] Error resolving token"
+            ),
+        }
+    }
+
+    info!(
+        n_tokens = tokens.len(),
+        n_resolved, "[TokenMetadata] Updated tokens"
+    );
+}
+
+// Reads symbol(), name() and decimals() of each token at `block_number`, returning one result per
+// token in the same order. Only a failing decimals() fails a token, since the swap math needs it
+// while symbol and name are optional in ERC-20. Fails as a whole when the node cannot be reached.
+pub async fn resolve_token_metadata(
+    client: &RPCClient,
+    tokens: &[Address],
+    block_number: u64,
+) -> Result<Vec<Result<TokenMetadata, String>>, String> {
+    let requests: Vec<Request> = tokens
+        .iter()
+        .flat_map(|&token| {
+            TOKEN_GETTERS.iter().map(move |getter| {
+                Request::eth_call(None, token, Bytes::from(id(getter).to_vec()), block_number)
+            })
+        })
+        .collect();
+
+    let results = client
+        .batch::<Bytes>(requests)
+        .await
+        .map_err(|err| err.to_string())?;
+
+    Ok(results
+        .chunks(TOKEN_GETTERS.len())
+        .map(|token_results| {
+            let output = |idx: usize| token_results[idx].as_ref().ok();
+            let decimals = match output(2).and_then(|output| output.get(..32)) {
+                Some(word) if U256::from_big_endian(word) <= U256::from(u8::MAX) => {
+                    U256::from_big_endian(word).as_u32() as u8
+                }
+                _ => return Err("decimals() failed or is out of range".to_string()),
+            };
+
+            Ok(TokenMetadata {
+                symbol: output(0).and_then(|output| decode_string(output)),
+                name: output(1).and_then(|output| decode_string(output)),
+                decimals,
+            })
+        })
+        .collect())
+}
+
+// Decodes a string getter result, which is either an ABI `string` or, for older tokens such as MKR,
+// a `bytes32` padded with zeros
+fn decode_string(output: &[u8]) -> Option<String> {
+    let value = if output.len() == 32 {
+        let end = output.iter().position(|&b| b == 0).unwrap_or(output.len());
+        String::from_utf8_lossy(&output[..end]).to_string()
+    } else {
+        abi::decode(&[ParamType::String], output)
+            .ok()?
+            .remove(0)
+            .into_string()?
+    };
+
+    let value = value.trim_matches(|c: char| c.is_control() || c.is_whitespace());
+    if value.is_empty() {
+        None
+    } else {
+        Some(value.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_abi_encoded_strings() {
+        let output = abi::encode(&[abi::Token::String("Wrapped Ether".to_string())]);
+        assert_eq!(decode_string(&output), Some("Wrapped Ether".to_string()));
+    }
+
+    #[test]
+    fn decodes_bytes32_strings() {
+        // MKR's symbol() returns a zero padded bytes32
+        let mut output = [0u8; 32];
+        output[..3].copy_from_slice(b"MKR");
+        assert_eq!(decode_string(&output), Some("MKR".to_string()));
+    }
+
+    #[test]
+    fn rejects_empty_and_invalid_strings() {
+        assert_eq!(decode_string(&[]), None);
+        assert_eq!(decode_string(&[0u8; 32]), None);
+        assert_eq!(
+            decode_string(&abi::encode(&[abi::Token::String(" \n".to_string())])),
+            None
+        );
+        assert_eq!(decode_string(&[0xff; 40]), None);
+    }
+}
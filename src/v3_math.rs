@@ -0,0 +1,305 @@
+use ethers_core::types::{U256, U512};
+
+pub const MIN_TICK: i32 = -887272;
+pub const MAX_TICK: i32 = 887272;
+const FEE_DENOMINATOR: u32 = 1_000_000;
+
+pub fn min_sqrt_ratio() -> U256 {
+    U256::from(4295128739_u64)
+}
+
+pub fn max_sqrt_ratio() -> U256 {
+    U256::from_dec_str("1461446703485210103287273052203988822378723970342").unwrap()
+}
+
+fn q96() -> U256 {
+    U256::one() << 96
+}
+
+// Liquidity change applied when the price crosses an initialized tick from left to right
+#[derive(Clone, Debug)]
+pub struct TickLiquidity {
+    pub index: i32,
+    pub liquidity_net: i128,
+}
+
+pub fn mul_div(a: U256, b: U256, denominator: U256) -> U256 {
+    U256::try_from(a.full_mul(b) / U512::from(denominator)).expect("mul_div overflow")
+}
+
+pub fn mul_div_rounding_up(a: U256, b: U256, denominator: U256) -> U256 {
+    let product = a.full_mul(b);
+    let denominator = U512::from(denominator);
+    let mut result = product / denominator;
+    if !(product % denominator).is_zero() {
+        result += U512::one();
+    }
+    U256::try_from(result).expect("mul_div_rounding_up overflow")
+}
+
+fn div_rounding_up(a: U256, b: U256) -> U256 {
+    let (quotient, remainder) = a.div_mod(b);
+    if remainder.is_zero() {
+        quotient
+    } else {
+        quotient + 1
+    }
+}
+
+// Port of TickMath.getSqrtRatioAtTick, returns sqrt(1.0001^tick) as a Q64.96
+pub fn get_sqrt_ratio_at_tick(tick: i32) -> U256 {
+    const MAGIC_NUMBERS: [(u32, &str); 19] = [
+        (0x2, "fff97272373d413259a46990580e213a"),
+        (0x4, "fff2e50f5f656932ef12357cf3c7fdcc"),
+        (0x8, "ffe5caca7e10e4e61c3624eaa0941cd0"),
+        (0x10, "ffcb9843d60f6159c9db58835c926644"),
+        (0x20, "ff973b41fa98c081472e6896dfb254c0"),
+        (0x40, "ff2ea16466c96a3843ec78b326b52861"),
+        (0x80, "fe5dee046a99a2a811c461f1969c3053"),
+        (0x100, "fcbe86c7900a88aedcffc83b479aa3a4"),
+        (0x200, "f987a7253ac413176f2b074cf7815e54"),
+        (0x400, "f3392b0822b70005940c7a398e4b70f3"),
+        (0x800, "e7159475a2c29b7443b29c7fa6e889d9"),
+        (0x1000, "d097f3bdfd2022b8845ad8f792aa5825"),
+        (0x2000, "a9f746462d870fdf8a65dc1f90e061e5"),
+        (0x4000, "70d869a156d2a1b890bb3df62baf32f7"),
+        (0x8000, "31be135f97d08fd981231505542fcfa6"),
+        (0x10000, "9aa508b5b7a84e1c677de54f3e99bc9"),
+        (0x20000, "5d6af8dedb81196699c329225ee604"),
+        (0x40000, "2216e584f5fa1ea926041bedfe98"),
+        (0x80000, "48a170391f7dc42444e8fa2"),
+    ];
+
+    let abs_tick = tick.unsigned_abs();
+    assert!(abs_tick <= MAX_TICK as u32, "tick out of range: {}", tick);
+
+    let mut ratio = if abs_tick & 0x1 != 0 {
+        U256::from_str_radix("fffcb933bd6fad37aa2d162d1a594001", 16).unwrap()
+    } else {
+        U256::one() << 128
+    };
+    for (mask, magic) in MAGIC_NUMBERS {
+        if abs_tick & mask != 0 {
+            ratio = (ratio * U256::from_str_radix(magic, 16).unwrap()) >> 128;
+        }
+    }
+    if tick > 0 {
+        ratio = U256::MAX / ratio;
+    }
+
+    let remainder = ratio % (U256::one() << 32);
+    (ratio >> 32) + if remainder.is_zero() { 0 } else { 1 }
+}
+
+pub fn get_amount0_delta(sqrt_a: U256, sqrt_b: U256, liquidity: U256, round_up: bool) -> U256 {
+    let (sqrt_a, sqrt_b) = if sqrt_a > sqrt_b {
+        (sqrt_b, sqrt_a)
+    } else {
+        (sqrt_a, sqrt_b)
+    };
+    let numerator1 = liquidity << 96;
+    let numerator2 = sqrt_b - sqrt_a;
+
+    if round_up {
+        div_rounding_up(mul_div_rounding_up(numerator1, numerator2, sqrt_b), sqrt_a)
+    } else {
+        mul_div(numerator1, numerator2, sqrt_b) / sqrt_a
+    }
+}
+
+pub fn get_amount1_delta(sqrt_a: U256, sqrt_b: U256, liquidity: U256, round_up: bool) -> U256 {
+    let (sqrt_a, sqrt_b) = if sqrt_a > sqrt_b {
+        (sqrt_b, sqrt_a)
+    } else {
+        (sqrt_a, sqrt_b)
+    };
+
+    if round_up {
+        mul_div_rounding_up(liquidity, sqrt_b - sqrt_a, q96())
+    } else {
+        mul_div(liquidity, sqrt_b - sqrt_a, q96())
+    }
+}
+
+fn get_next_sqrt_price_from_amount0_rounding_up(
+    sqrt_price: U256,
+    liquidity: U256,
+    amount: U256,
+) -> U256 {
+    if amount.is_zero() {
+        return sqrt_price;
+    }
+
+    let numerator1 = liquidity << 96;
+    let (product, overflowed) = amount.overflowing_mul(sqrt_price);
+    if !overflowed {
+        let (denominator, overflowed) = numerator1.overflowing_add(product);
+        if !overflowed {
+            return mul_div_rounding_up(numerator1, sqrt_price, denominator);
+        }
+    }
+
+    div_rounding_up(numerator1, numerator1 / sqrt_price + amount)
+}
+
+fn get_next_sqrt_price_from_amount1_rounding_down(
+    sqrt_price: U256,
+    liquidity: U256,
+    amount: U256,
+) -> U256 {
+    let quotient = if amount < (U256::one() << 160) {
+        (amount << 96) / liquidity
+    } else {
+        mul_div(amount, q96(), liquidity)
+    };
+
+    sqrt_price + quotient
+}
+
+fn get_next_sqrt_price_from_input(
+    sqrt_price: U256,
+    liquidity: U256,
+    amount_in: U256,
+    zero_for_one: bool,
+) -> U256 {
+    if zero_for_one {
+        get_next_sqrt_price_from_amount0_rounding_up(sqrt_price, liquidity, amount_in)
+    } else {
+        get_next_sqrt_price_from_amount1_rounding_down(sqrt_price, liquidity, amount_in)
+    }
+}
+
+pub struct SwapStep {
+    pub sqrt_price_next: U256,
+    pub amount_in: U256,
+    pub amount_out: U256,
+    pub fee_amount: U256,
+}
+
+// Port of SwapMath.computeSwapStep, restricted to exact input swaps
+pub fn compute_swap_step(
+    sqrt_price_current: U256,
+    sqrt_price_target: U256,
+    liquidity: U256,
+    amount_remaining: U256,
+    fee_pips: u32,
+) -> SwapStep {
+    let zero_for_one = sqrt_price_current >= sqrt_price_target;
+    let amount_remaining_less_fee = mul_div(
+        amount_remaining,
+        U256::from(FEE_DENOMINATOR - fee_pips),
+        U256::from(FEE_DENOMINATOR),
+    );
+
+    let max_amount_in = if zero_for_one {
+        get_amount0_delta(sqrt_price_target, sqrt_price_current, liquidity, true)
+    } else {
+        get_amount1_delta(sqrt_price_current, sqrt_price_target, liquidity, true)
+    };
+    let sqrt_price_next = if amount_remaining_less_fee >= max_amount_in {
+        sqrt_price_target
+    } else {
+        get_next_sqrt_price_from_input(
+            sqrt_price_current,
+            liquidity,
+            amount_remaining_less_fee,
+            zero_for_one,
+        )
+    };
+
+    let reached_target = sqrt_price_next == sqrt_price_target;
+    let (amount_in, amount_out) = if zero_for_one {
+        (
+            if reached_target {
+                max_amount_in
+            } else {
+                get_amount0_delta(sqrt_price_next, sqrt_price_current, liquidity, true)
+            },
+            get_amount1_delta(sqrt_price_next, sqrt_price_current, liquidity, false),
+        )
+    } else {
+        (
+            if reached_target {
+                max_amount_in
+            } else {
+                get_amount1_delta(sqrt_price_current, sqrt_price_next, liquidity, true)
+            },
+            get_amount0_delta(sqrt_price_current, sqrt_price_next, liquidity, false),
+        )
+    };
+
+    let fee_amount = if reached_target {
+        mul_div_rounding_up(
+            amount_in,
+            U256::from(fee_pips),
+            U256::from(FEE_DENOMINATOR - fee_pips),
+        )
+    } else {
+        amount_remaining - amount_in
+    };
+
+    SwapStep {
+        sqrt_price_next,
+        amount_in,
+        amount_out,
+        fee_amount,
+    }
+}
+
+// Finds the closest initialized tick in the direction of the swap, `ticks` must be sorted by index
+pub fn next_initialized_tick(
+    ticks: &[TickLiquidity],
+    tick: i32,
+    zero_for_one: bool,
+) -> Option<&TickLiquidity> {
+    let idx = ticks.partition_point(|t| t.index <= tick);
+    if zero_for_one {
+        idx.checked_sub(1).map(|idx| &ticks[idx])
+    } else {
+        ticks.get(idx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u256(value: &str) -> U256 {
+        U256::from_dec_str(value).unwrap()
+    }
+
+    #[test]
+    fn sqrt_ratio_at_tick_matches_tick_math() {
+        assert_eq!(get_sqrt_ratio_at_tick(0), q96());
+        assert_eq!(
+            get_sqrt_ratio_at_tick(1),
+            u256("79232123823359799118286999568")
+        );
+        assert_eq!(
+            get_sqrt_ratio_at_tick(-1),
+            u256("79224201403219477170569942574")
+        );
+        assert_eq!(get_sqrt_ratio_at_tick(MIN_TICK), min_sqrt_ratio());
+        assert_eq!(get_sqrt_ratio_at_tick(MAX_TICK), max_sqrt_ratio());
+    }
+
+    // "exact amount in that gets capped at price target in one for zero" of SwapMath.spec.ts
+    #[test]
+    fn swap_step_is_capped_at_price_target() {
+        let price = q96();
+        let price_target = u256("79623317895830914510639640423");
+        let step = compute_swap_step(
+            price,
+            price_target,
+            U256::exp10(18) * 2,
+            U256::exp10(18),
+            600,
+        );
+
+        assert_eq!(step.sqrt_price_next, price_target);
+        assert_eq!(step.amount_in, u256("9975124224178055"));
+        assert_eq!(step.fee_amount, u256("5988667735148"));
+        assert_eq!(step.amount_out, u256("9925619580021728"));
+        assert!(step.amount_in + step.fee_amount < U256::exp10(18));
+    }
+}